@@ -1,15 +1,165 @@
+#[cfg(test)]
 use std::io;
 
+/// Number of pixels stored in every word of the packed pixel storage
+pub const WORD_BITS: usize = 64;
+
+/// return the number of words needed to store `bits` bits
+pub fn words_for(bits: usize) -> usize {
+    (bits + WORD_BITS - 1) / WORD_BITS
+}
+
+/// return the mask of the meaningful bits in the last word of a row `bits` long
+pub fn last_word_mask(bits: usize) -> u64 {
+    match bits % WORD_BITS {
+        0 => !0,
+        rem => !0 << (WORD_BITS - rem),
+    }
+}
+
+/// return the mask of the meaningful bits in the last byte of a row `bits` long
+pub fn last_byte_mask(bits: usize) -> u8 {
+    match bits % 8 {
+        0 => !0,
+        rem => !0 << (8 - rem),
+    }
+}
+
+/// return the bit at `index`, bits are stored from the most significant of every word
+pub fn get_bit(words: &[u64], index: usize) -> bool {
+    words[index / WORD_BITS] & (1 << (WORD_BITS - 1 - index % WORD_BITS)) != 0
+}
+
+/// set the bit at `index` to `value`
+pub fn set_bit(words: &mut [u64], index: usize, value: bool) {
+    let bit = 1 << (WORD_BITS - 1 - index % WORD_BITS);
+    if value {
+        words[index / WORD_BITS] |= bit;
+    } else {
+        words[index / WORD_BITS] &= !bit;
+    }
+}
+
+/// return the 64 bits starting at bit `start`, bits past the end of `words` are zero
+pub fn get_word(words: &[u64], start: usize) -> u64 {
+    let index = start / WORD_BITS;
+    let offset = start % WORD_BITS;
+    let high = words.get(index).copied().unwrap_or(0);
+    if offset == 0 {
+        high
+    } else {
+        let low = words.get(index + 1).copied().unwrap_or(0);
+        (high << offset) | (low >> (WORD_BITS - offset))
+    }
+}
+
+/// or the 64 bits of `word` in `words` starting at bit `start`, bits past the end are discarded
+pub fn or_word(words: &mut [u64], start: usize, word: u64) {
+    let index = start / WORD_BITS;
+    let offset = start % WORD_BITS;
+    if let Some(high) = words.get_mut(index) {
+        *high |= word >> offset;
+    }
+    if offset != 0 {
+        if let Some(low) = words.get_mut(index + 1) {
+            *low |= word << (WORD_BITS - offset);
+        }
+    }
+}
+
+/// clear the bits set in `word` in `words` starting at bit `start`, bits past the end are
+/// discarded
+pub fn clear_word(words: &mut [u64], start: usize, word: u64) {
+    let index = start / WORD_BITS;
+    let offset = start % WORD_BITS;
+    if let Some(high) = words.get_mut(index) {
+        *high &= !(word >> offset);
+    }
+    if offset != 0 {
+        if let Some(low) = words.get_mut(index + 1) {
+            *low &= !(word << (WORD_BITS - offset));
+        }
+    }
+}
+
+/// or `len` bits of `src` starting at `src_start` in `dst` starting at `dst_start`
+pub fn copy_bits(src: &[u64], src_start: usize, dst: &mut [u64], dst_start: usize, len: usize) {
+    for done in (0..len).step_by(WORD_BITS) {
+        let word = get_word(src, src_start + done) & last_word_mask((len - done).min(WORD_BITS));
+        or_word(dst, dst_start + done, word);
+    }
+}
+
+/// set `len` bits starting at `start`
+pub fn set_range(words: &mut [u64], start: usize, len: usize) {
+    for done in (0..len).step_by(WORD_BITS) {
+        or_word(
+            words,
+            start + done,
+            last_word_mask((len - done).min(WORD_BITS)),
+        );
+    }
+}
+
+/// clear `len` bits starting at `start`
+pub fn clear_range(words: &mut [u64], start: usize, len: usize) {
+    for done in (0..len).step_by(WORD_BITS) {
+        clear_word(
+            words,
+            start + done,
+            last_word_mask((len - done).min(WORD_BITS)),
+        );
+    }
+}
+
+/// transpose the 64x64 bit matrix `block`, where every word is a row and the most significant
+/// bit is the first column, swapping blocks of decreasing size across the diagonal
+pub fn transpose(block: &mut [u64; WORD_BITS]) {
+    let mut size = WORD_BITS / 2;
+    let mut mask: u64 = 0x0000_0000_FFFF_FFFF;
+    while size != 0 {
+        let mut k = 0;
+        while k < WORD_BITS {
+            // swap the right part of row k with the left part of row k + size
+            let swap = (block[k] ^ (block[k + size] >> size)) & mask;
+            block[k] ^= swap;
+            block[k + size] ^= swap << size;
+            k = (k + size + 1) & !size;
+        }
+        size /= 2;
+        mask ^= mask << size;
+    }
+}
+
+/// fill `words` with `bytes`, the first byte becoming the most significant of the first word
+pub fn bytes_to_words(bytes: &[u8], words: &mut [u64]) {
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks(8)) {
+        let mut buffer = [0u8; 8];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        *word = u64::from_be_bytes(buffer);
+    }
+}
+
+/// fill `bytes` with `words`, the most significant byte of the first word becoming the first byte
+pub fn words_to_bytes(words: &[u64], bytes: &mut [u8]) {
+    for (chunk, word) in bytes.chunks_mut(8).zip(words.iter()) {
+        let len = chunk.len();
+        chunk.copy_from_slice(&word.to_be_bytes()[..len]);
+    }
+}
+
 /// Bitwise stream reader
+#[cfg(test)]
 pub struct BitStreamReader<'a> {
     buffer: [u8; 1],
     offset: u8,
     reader: &'a mut dyn io::Read,
 }
 
+#[cfg(test)]
 impl<'a> BitStreamReader<'a> {
     /// Create a new BitStreamReader that reads bitwise from a given reader
-    pub fn new(reader: &'a mut dyn io::Read) -> BitStreamReader {
+    pub fn new(reader: &'a mut dyn io::Read) -> BitStreamReader<'a> {
         BitStreamReader {
             buffer: [0u8],
             reader,
@@ -42,15 +192,17 @@ impl<'a> BitStreamReader<'a> {
 }
 
 /// Bitwise stream writer
+#[cfg(test)]
 pub struct BitStreamWriter<'a> {
     buffer: [u8; 1],
     offset: u8,
     writer: &'a mut dyn io::Write,
 }
 
+#[cfg(test)]
 impl<'a> BitStreamWriter<'a> {
     /// Create a new BitStreamWriter that writes bitwise to a given writer
-    pub fn new(writer: &'a mut dyn io::Write) -> BitStreamWriter {
+    pub fn new(writer: &'a mut dyn io::Write) -> BitStreamWriter<'a> {
         BitStreamWriter {
             buffer: [0u8],
             writer,
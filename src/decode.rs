@@ -1,86 +1,872 @@
-use crate::bit::BitStreamReader;
-use crate::{check_size, Bmp, BmpError, BmpHeader, B, HEADER_SIZE, M};
-use std::convert::TryFrom;
-use std::io::{Cursor, Read};
+use crate::bit;
+use crate::threshold;
+use crate::{
+    Bmp, BmpError, BmpHeader, BmpInfo, BmpRef, ChannelMasks, ColorSpace, Compression, DecodeMode,
+    DecodeWarning, DibHeader, InkMapping, Limits, Palette, ReadOptions, Resolution, Rgb, RleState,
+    RowDecoder, RowOrder, Threshold, B, FILE_HEADER_SIZE, M,
+};
+use std::io::{ErrorKind, Read};
+use std::ops::Range;
+
+/// BITMAPCOREHEADER palette colors are 3 bytes each, the other headers use 4 bytes
+const CORE_COLOR_SIZE: u32 = 3;
+const COLOR_SIZE: u32 = 4;
+
+/// max number of colors declared in the palette
+const MAX_COLORS: u32 = 256;
+
+/// sanity limit of bytes skipped between the palette and the pixel data
+const MAX_GAP_SIZE: u32 = 1 << 20;
+
+/// `LCS_PROFILE_EMBEDDED` colour space type, the ICC profile is contained in the file
+const PROFILE_EMBEDDED: u32 = 0x4D42_4544;
 
 impl Bmp {
+    /// Read the monochrome bitmap from a Read type, such a File, enforcing the given `limits`
+    pub fn read_with_limits<T: Read>(from: T, limits: &Limits) -> Result<Self, BmpError> {
+        let options = ReadOptions {
+            limits: *limits,
+            ..Default::default()
+        };
+        Bmp::read_with(from, &options)
+    }
+
     /// Read the monochrome bitmap from a Read type, such a File
-    /// note that File read are not buffered and may be slow, see [Read](std::io::Read) Trait
-    pub fn read<T: Read>(mut from: T) -> Result<Self, BmpError> {
-        let mut header_bytes = [0u8; HEADER_SIZE as usize];
-        from.read_exact(&mut header_bytes)?;
-        let header = BmpHeader::read(Cursor::new(&mut header_bytes.to_vec()))?;
-        let width = header.width;
-        let height = header.height;
-        let padding = header.padding() as u8;
-        let mut reader = BitStreamReader::new(&mut from);
-        let mut rows = Vec::with_capacity(height as usize);
-        let mut row = Vec::with_capacity(width as usize);
-        for _ in 0..height as usize {
-            for _ in 0..width as usize {
-                if reader.read(1)? == 1 {
-                    row.push(!header.bg_is_zero());
-                } else {
-                    row.push(header.bg_is_zero());
+    /// every row is read with a single call, however File read are not buffered and may be slow
+    /// for big images, see [Read](std::io::Read) Trait
+    /// The darker palette color is considered ink, thus `true` pixels
+    pub fn read<T: Read>(from: T) -> Result<Self, BmpError> {
+        Bmp::read_with(from, &ReadOptions::default())
+    }
+
+    /// Read the monochrome bitmap from a Read type, such a File, customizing the decoding with
+    /// `options`
+    pub fn read_with<T: Read>(from: T, options: &ReadOptions) -> Result<Self, BmpError> {
+        Bmp::read_with_warnings(from, options).map(|(bmp, _)| bmp)
+    }
+
+    /// Read the monochrome bitmap like [`Bmp::read_with`], returning also the problems found
+    /// in [`DecodeMode::Lenient`]
+    pub fn read_with_warnings<T: Read>(
+        from: T,
+        options: &ReadOptions,
+    ) -> Result<(Self, Vec<DecodeWarning>), BmpError> {
+        let mut decoder = RowDecoder::with_options(from, options)?;
+        options
+            .limits
+            .check(decoder.header.width, decoder.header.height)?;
+        let mut bmp = Bmp::zeroed_for(&decoder.header);
+        for n in 0..decoder.header.height as usize {
+            let index = decoder.header.row_index(n);
+            let bytes = decoder.next_row()?.expect("height rows are available");
+            bit::bytes_to_words(bytes, bmp.row_mut(index));
+        }
+
+        Ok((bmp, decoder.warnings))
+    }
+
+    /// Read a bitmap of 1, 4, 8, 16, 24 or 32 bits per pixel, uncompressed or with BI_BITFIELDS
+    /// masks, such a screenshot, setting the pixels darker than `threshold`
+    /// The alpha channel is ignored and the default [`Limits`] are enforced
+    pub fn read_thresholded<T: Read>(from: T, threshold: Threshold) -> Result<Self, BmpError> {
+        Bmp::read_thresholded_with_limits(from, threshold, &Limits::default())
+    }
+
+    /// Read a bitmap like [`Bmp::read_thresholded`] enforcing the given `limits`
+    /// [`Threshold::Otsu`] keeps the luminance of every pixel until the whole image is read,
+    /// using a byte per pixel
+    pub fn read_thresholded_with_limits<T: Read>(
+        mut from: T,
+        threshold: Threshold,
+        limits: &Limits,
+    ) -> Result<Self, BmpError> {
+        let mut info = BmpInfo::read(&mut from)?;
+        if info.planes != 1u16 {
+            return Err(BmpError::InvalidPlanes(info.planes));
+        }
+        let bits_per_pixel = info.bits_per_pixel;
+        let masks = match (bits_per_pixel, info.compression) {
+            (1, Compression::Uncompressed)
+            | (4, Compression::Uncompressed)
+            | (8, Compression::Uncompressed)
+            | (24, Compression::Uncompressed) => None,
+            (16, Compression::Uncompressed) => Some(ChannelMasks {
+                red: 0x7C00,
+                green: 0x03E0,
+                blue: 0x001F,
+                alpha: 0,
+            }),
+            (32, Compression::Uncompressed) => Some(ChannelMasks {
+                red: 0x00FF_0000,
+                green: 0x0000_FF00,
+                blue: 0x0000_00FF,
+                alpha: 0,
+            }),
+            (16, Compression::Bitfields) | (32, Compression::Bitfields) => info.masks,
+            (1, compression)
+            | (4, compression)
+            | (8, compression)
+            | (16, compression)
+            | (24, compression)
+            | (32, compression) => return Err(BmpError::UnsupportedCompression(compression)),
+            (bits_per_pixel, _) => return Err(BmpError::UnsupportedBitsPerPixel(bits_per_pixel)),
+        };
+        if bits_per_pixel <= 8 && info.palette.is_empty() {
+            return Err(BmpError::InvalidPalette);
+        }
+        let palette: Vec<u8> = info
+            .palette
+            .iter()
+            .map(|c| threshold::luminance(*c))
+            .collect();
+        let width = info.width;
+        let height = info.height;
+        limits.check(width, height)?;
+        info.skip_to_pixels(&mut from)?;
+
+        let mut bmp = Bmp::zeroed(width, height);
+        bmp.meta.color_space = info.color_space;
+        bmp.meta.resolution = info.resolution;
+        // a fixed threshold binarises every row as it's read, Otsu needs all the luminances
+        let limit = match threshold {
+            Threshold::Fixed(_) => Some(threshold::limit(threshold, &[])),
+            Threshold::Otsu => None,
+        };
+        let mut luminances = match limit {
+            Some(_) => vec![],
+            None => vec![0u8; width as usize * height as usize],
+        };
+        let stride = (width as usize * bits_per_pixel as usize + 31) / 32 * 4;
+        let mut buffer = vec![0u8; stride];
+        let mut row = vec![0u8; width as usize];
+        for n in 0..height as usize {
+            from.read_exact(&mut buffer).map_err(|e| truncated(n, e))?;
+            for (j, luminance) in row.iter_mut().enumerate() {
+                *luminance = pixel_luminance(&buffer, j, bits_per_pixel, &palette, masks)?;
+            }
+            let i = info.row_order.row_index(n, height);
+            match limit {
+                Some(limit) => set_darker(bmp.row_mut(i), &row, limit),
+                None => luminances[i * row.len()..(i + 1) * row.len()].copy_from_slice(&row),
+            }
+        }
+
+        if limit.is_none() {
+            let limit = threshold::limit(threshold, &luminances);
+            for (i, row) in luminances.chunks(width as usize).enumerate() {
+                set_darker(bmp.row_mut(i), row, limit);
+            }
+        }
+
+        Ok(bmp)
+    }
+}
+
+/// set the bits of `words` whose luminance is lower than `limit`
+fn set_darker(words: &mut [u64], luminances: &[u8], limit: u16) {
+    for (j, luminance) in luminances.iter().enumerate() {
+        if (*luminance as u16) < limit {
+            bit::set_bit(words, j, true);
+        }
+    }
+}
+
+/// return the luminance of the pixel `j` of `row`, encoded with `bits_per_pixel` bits, using the
+/// luminance of the `palette` colors or the channel `masks`
+fn pixel_luminance(
+    row: &[u8],
+    j: usize,
+    bits_per_pixel: u16,
+    palette: &[u8],
+    masks: Option<ChannelMasks>,
+) -> Result<u8, BmpError> {
+    let value = match bits_per_pixel {
+        1 => ((row[j / 8] >> (7 - j % 8)) & 1) as u32,
+        4 => ((row[j / 2] >> (4 - j % 2 * 4)) & 0xF) as u32,
+        8 => row[j] as u32,
+        16 => u16::from_le_bytes([row[j * 2], row[j * 2 + 1]]) as u32,
+        24 => u32::from_le_bytes([row[j * 3], row[j * 3 + 1], row[j * 3 + 2], 0]),
+        _ => u32::from_le_bytes([row[j * 4], row[j * 4 + 1], row[j * 4 + 2], row[j * 4 + 3]]),
+    };
+    if bits_per_pixel <= 8 {
+        return palette
+            .get(value as usize)
+            .cloned()
+            .ok_or(BmpError::InvalidPalette);
+    }
+    let color = match masks {
+        Some(masks) => Rgb {
+            r: threshold::channel(value, masks.red),
+            g: threshold::channel(value, masks.green),
+            b: threshold::channel(value, masks.blue),
+        },
+        None => Rgb::from_u32(value),
+    };
+    Ok(threshold::luminance(color))
+}
+
+impl Bmp {
+    /// Creates a Bmp with all the pixels white, sized and with the metadata read from `header`
+    fn zeroed_for(header: &BmpHeader) -> Bmp {
+        let mut bmp = Bmp::zeroed(header.width, header.height);
+        bmp.meta.color_space = header.color_space.clone();
+        bmp.meta.palette = Some(header.palette());
+        bmp.meta.resolution = header.resolution;
+        bmp
+    }
+}
+
+impl<'a> BmpRef<'a> {
+    /// Validate the monochrome bitmap encoded in `bytes`, erroring like [`Bmp::read`] if the
+    /// header is invalid or the pixel data is truncated, compressed bitmaps are not supported
+    pub fn new(bytes: &'a [u8]) -> Result<Self, BmpError> {
+        BmpRef::with_options(bytes, &ReadOptions::default())
+    }
+
+    /// Validate the monochrome bitmap like [`BmpRef::new`] customizing the decoding with
+    /// `options`, truncated pixel data is an error even in [`DecodeMode::Lenient`]
+    pub fn with_options(bytes: &'a [u8], options: &ReadOptions) -> Result<Self, BmpError> {
+        let info = BmpInfo::read(bytes)?;
+        let header = BmpHeader::from_info(&info, options.ink)?;
+        if header.compression != Compression::Uncompressed {
+            return Err(BmpError::UnsupportedCompression(header.compression));
+        }
+        // the pixels are not copied, only the width is limited
+        options.limits.check_row(header.width, header.height, 0)?;
+        if let (DecodeMode::Strict, Some(warning)) = (options.mode, header.size_warnings().first())
+        {
+            return Err((*warning).into());
+        }
+        let pixels = bytes.get(info.pixel_offset as usize..).unwrap_or(&[]);
+        let stride = header.bytes_per_row() as usize + header.padding() as usize;
+        let rows = pixels.len() / stride;
+        if rows < header.height as usize {
+            let e = std::io::Error::from(ErrorKind::UnexpectedEof);
+            return Err(truncated(rows, e));
+        }
+        let pixels = &pixels[..stride * header.height as usize];
+        let profile = info.profile_range().map(|range| &bytes[range]);
+        Ok(BmpRef {
+            header,
+            pixels,
+            profile,
+        })
+    }
+
+    /// return the height in pixel
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    /// return the width in pixel
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
+
+    /// return the palette with background and foreground colors
+    pub fn palette(&self) -> Palette {
+        self.header.palette()
+    }
+
+    /// return the resolution, if declared in the header
+    pub fn resolution(&self) -> Option<Resolution> {
+        self.header.resolution
+    }
+
+    /// return the bytes of row `i`, where 0 is the upper row, padding excluded
+    fn row(&self, i: u32) -> &'a [u8] {
+        let stride = self.header.bytes_per_row() as usize + self.header.padding() as usize;
+        let start = self.header.row_index(i as usize) * stride;
+        &self.pixels[start..start + self.header.bytes_per_row() as usize]
+    }
+
+    /// return the pixel situated at (i,j), where (0,0) is the upper-left corner
+    /// panics if i >= self.height() || j >= self.width()
+    pub fn get(&self, i: u32, j: u32) -> bool {
+        assert!(
+            i < self.header.height && j < self.header.width,
+            "pixel ({}, {}) out of bounds",
+            i,
+            j
+        );
+        let byte = self.row(i)[j as usize / 8];
+        (byte & (0x80 >> (j % 8)) != 0) != self.header.bg_is_zero()
+    }
+
+    /// return an iterator over the rows, from the upper to the lower one, every row being an
+    /// iterator over its pixels from left to right, `true` being dark
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = bool> + 'a> + 'a {
+        let pixels = self.pixels;
+        let bytes_per_row = self.header.bytes_per_row() as usize;
+        let stride = bytes_per_row + self.header.padding() as usize;
+        let width = self.header.width as usize;
+        let bg_is_zero = self.header.bg_is_zero();
+        let height = self.header.height;
+        let row_order = self.header.row_order;
+        (0..height as usize).map(move |i| {
+            let start = row_order.row_index(i, height) * stride;
+            let row = &pixels[start..start + bytes_per_row];
+            (0..width).map(move |j| (row[j / 8] & (0x80 >> (j % 8)) != 0) != bg_is_zero)
+        })
+    }
+
+    /// return an owned [`Bmp`] with the same pixels and metadata
+    pub fn to_bmp(&self) -> Bmp {
+        let mut bmp = Bmp::zeroed_for(&self.header);
+        if let (Some(color_space), Some(profile)) = (bmp.meta.color_space.as_mut(), self.profile) {
+            color_space.profile = Some(profile.to_vec());
+        }
+        let mask = bit::last_word_mask(self.header.width as usize);
+        for i in 0..self.header.height {
+            let words = bmp.row_mut(i as usize);
+            bit::bytes_to_words(self.row(i), words);
+            if self.header.bg_is_zero() {
+                for word in words.iter_mut() {
+                    *word = !*word;
                 }
             }
-            reader.read((8 - (width % 8) as u8) % 8)?; // finish reading the full byte
-            reader.read(padding * 8)?; // read the padding such that every row is multiple of 4 bytes
-            rows.push(row.clone());
-            row.clear();
+            if let Some(last) = words.last_mut() {
+                *last &= mask;
+            }
         }
-        rows.reverse();
+        bmp
+    }
+}
 
-        Ok(Bmp { rows })
+impl<R: Read> RowDecoder<R> {
+    /// Read the header of the monochrome bitmap from `from`, the rows are then returned by
+    /// [`RowDecoder::next_row`]
+    pub fn new(from: R) -> Result<Self, BmpError> {
+        RowDecoder::with_options(from, &ReadOptions::default())
+    }
+
+    /// Read the header like [`RowDecoder::new`] customizing the decoding with `options`
+    pub fn with_options(mut from: R, options: &ReadOptions) -> Result<Self, BmpError> {
+        let header = BmpHeader::read(&mut from, options.ink)?;
+        let row_bytes = header.bytes_per_row() as u64 + header.padding() as u64;
+        // the pixels returned by next_row_pixels take a byte each
+        let row_bytes = row_bytes.max(header.width as u64);
+        options
+            .limits
+            .check_row(header.width, header.height, row_bytes)?;
+        let warnings = header.size_warnings();
+        if let (DecodeMode::Strict, Some(warning)) = (options.mode, warnings.first()) {
+            return Err((*warning).into());
+        }
+        let buffer = vec![0u8; header.bytes_per_row() as usize + header.padding() as usize];
+        Ok(RowDecoder {
+            from,
+            header,
+            buffer,
+            pixels: vec![],
+            row: 0,
+            rle: RleState::default(),
+            mode: options.mode,
+            warnings,
+        })
+    }
+
+    /// return the problems found so far decoding in [`DecodeMode::Lenient`]
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        &self.warnings
+    }
+
+    /// return the width in pixel
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
+
+    /// return the height in pixel, that is the number of rows returned
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    /// return the order of the rows, with [`RowOrder::BottomUp`] the first returned row is the
+    /// lower one
+    pub fn row_order(&self) -> RowOrder {
+        self.header.row_order
+    }
+
+    /// return the palette with background and foreground colors
+    pub fn palette(&self) -> Palette {
+        self.header.palette()
+    }
+
+    /// return the resolution, if declared in the header
+    pub fn resolution(&self) -> Option<Resolution> {
+        self.header.resolution
+    }
+
+    /// return the next row, as packed bytes with the most significant bit of the first byte
+    /// being the left pixel, set bits are the dark ones and the bits past the width are zero
+    /// return `None` after the last row
+    pub fn next_row(&mut self) -> Result<Option<&[u8]>, BmpError> {
+        if self.row == self.header.height {
+            return Ok(None);
+        }
+        let missing = self.ended();
+        let read = if missing {
+            Ok(())
+        } else if self.header.compression == Compression::Uncompressed {
+            self.read_row()
+        } else {
+            self.read_rle_row()
+        };
+        if let Err(e) = read {
+            if self.mode == DecodeMode::Strict || e.kind() != ErrorKind::UnexpectedEof {
+                return Err(truncated(self.row as usize, e));
+            }
+            self.warnings
+                .push(DecodeWarning::TruncatedData { row: self.row });
+        }
+        self.row += 1;
+        let bytes = &mut self.buffer[..self.header.bytes_per_row() as usize];
+        if missing {
+            for byte in bytes.iter_mut() {
+                *byte = 0;
+            }
+        } else if self.header.bg_is_zero() {
+            for byte in bytes.iter_mut() {
+                *byte = !*byte;
+            }
+        }
+        if let Some(last) = bytes.last_mut() {
+            *last &= bit::last_byte_mask(self.header.width as usize);
+        }
+        Ok(Some(bytes))
+    }
+
+    /// return true if the pixel data ended before the current row
+    fn ended(&self) -> bool {
+        self.warnings
+            .iter()
+            .any(|w| matches!(w, DecodeWarning::TruncatedData { .. }))
+    }
+
+    /// read the next row of an uncompressed bitmap in the buffer, if the data ends the missing
+    /// pixels are background
+    fn read_row(&mut self) -> std::io::Result<()> {
+        if self.mode == DecodeMode::Strict {
+            return self.from.read_exact(&mut self.buffer);
+        }
+        let mut len = 0;
+        while len < self.buffer.len() {
+            match self.from.read(&mut self.buffer[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if len < self.buffer.len() {
+            let background = if self.header.bg_is_zero() { !0 } else { 0 };
+            for byte in self.buffer[len..].iter_mut() {
+                *byte = background;
+            }
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// decode the next row of a BI_RLE8 or BI_RLE4 compressed bitmap in the buffer, runs past
+    /// the width are discarded and unknown palette indexes are light
+    fn read_rle_row(&mut self) -> std::io::Result<()> {
+        let RowDecoder {
+            from,
+            header,
+            buffer,
+            rle,
+            ..
+        } = self;
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+        if rle.end {
+            return Ok(());
+        }
+        if rle.skipped_rows > 0 {
+            rle.skipped_rows -= 1;
+            return Ok(());
+        }
+        let width = header.width as usize;
+        let rle4 = header.compression == Compression::Rle4;
+        let dark_indexes = &header.dark_indexes;
+        let mut set = |x: usize, index: u8| {
+            if x < width && dark_indexes.get(index as usize) == Some(&true) {
+                buffer[x / 8] |= 0x80 >> (x % 8);
+            }
+        };
+        let mut absolute = [0u8; 256];
+        loop {
+            let mut pair = [0u8; 2];
+            from.read_exact(&mut pair)?;
+            match (pair[0], pair[1]) {
+                (0, 0) => {
+                    // end of line
+                    rle.x = 0;
+                    return Ok(());
+                }
+                (0, 1) => {
+                    // end of bitmap, the remaining pixels are light
+                    rle.end = true;
+                    return Ok(());
+                }
+                (0, 2) => {
+                    // delta, move right and down
+                    from.read_exact(&mut pair)?;
+                    let (dx, dy) = (pair[0], pair[1]);
+                    rle.x += dx as usize;
+                    if dy > 0 {
+                        rle.skipped_rows = dy as u32 - 1;
+                        return Ok(());
+                    }
+                }
+                (0, len) => {
+                    // absolute mode, `len` indexes follow, padded to 16 bits
+                    let len = len as usize;
+                    let bytes = if rle4 { (len + 1) / 2 } else { len };
+                    let padded = bytes + bytes % 2;
+                    from.read_exact(&mut absolute[..padded])?;
+                    for k in 0..len {
+                        let index = if rle4 {
+                            nibble(absolute[k / 2], k)
+                        } else {
+                            absolute[k]
+                        };
+                        set(rle.x, index);
+                        rle.x += 1;
+                    }
+                }
+                (count, value) => {
+                    // encoded mode, `count` pixels of the index, or of the two alternating
+                    // indexes for BI_RLE4
+                    for k in 0..count as usize {
+                        let index = if rle4 { nibble(value, k) } else { value };
+                        set(rle.x, index);
+                        rle.x += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// return the next row like [`RowDecoder::next_row`] with a `bool` for every pixel, `true`
+    /// being dark
+    pub fn next_row_pixels(&mut self) -> Result<Option<&[bool]>, BmpError> {
+        let width = self.header.width as usize;
+        if self.next_row()?.is_none() {
+            return Ok(None);
+        }
+        let bytes = &self.buffer;
+        self.pixels.clear();
+        self.pixels
+            .extend((0..width).map(|j| bytes[j / 8] & (0x80 >> (j % 8)) != 0));
+        Ok(Some(&self.pixels))
     }
 }
 
-impl BmpHeader {
-    /// read the BmpHeader from read Trait `T`
-    /// returns `BmpError::Size` for error related to the declared bmp size, see `check_size`
-    /// and `BmpError::Header` for any other error
+/// return the high nibble of `byte` for even `k`, the low one otherwise
+fn nibble(byte: u8, k: usize) -> u8 {
+    if k % 2 == 0 {
+        byte >> 4
+    } else {
+        byte & 0xF
+    }
+}
+
+/// return [`BmpError::TruncatedData`] if the error is an unexpected end of file
+fn truncated(row: usize, e: std::io::Error) -> BmpError {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        BmpError::TruncatedData {
+            row: row as u32,
+            source: e,
+        }
+    } else {
+        BmpError::Io(e)
+    }
+}
+
+impl BmpInfo {
+    /// Read the information contained in the header of a BMP file, leaving `from` right after
+    /// the palette, without reading the pixel data.
+    /// The header may be of any variant, from the OS/2 BITMAPCOREHEADER to BITMAPV5HEADER and
+    /// the image may be of any size or format, including the ones not supported by [`Bmp::read`]
+    /// returns an error if the file isn't a BMP or the header is inconsistent
     pub fn read<T: Read>(mut from: T) -> Result<Self, BmpError> {
         let b = ReadLE::read_u8(&mut from)?;
         let m = ReadLE::read_u8(&mut from)?;
-        let _total_size = ReadLE::read_u32(&mut from)?;
+        let file_size = ReadLE::read_u32(&mut from)?;
         let _creator1 = ReadLE::read_u16(&mut from)?;
         let _creator2 = ReadLE::read_u16(&mut from)?;
         let pixel_offset = ReadLE::read_u32(&mut from)?;
-        let dib_header = ReadLE::read_u32(&mut from)?;
-        let width = ReadLE::read_u32(&mut from)?;
-        let height = ReadLE::read_u32(&mut from)?;
+        let dib_size = ReadLE::read_u32(&mut from)?;
+        if b != B || m != M {
+            return Err(BmpError::BadMagic([b, m]));
+        }
+        let dib_header =
+            DibHeader::from_size(dib_size).ok_or(BmpError::UnsupportedDibHeader(dib_size))?;
+        let is_core = dib_header == DibHeader::Core;
+        let (width, height) = if is_core {
+            // BITMAPCOREHEADER dimensions are 16 bits unsigned
+            let width = ReadLE::read_u16(&mut from)?;
+            let height = ReadLE::read_u16(&mut from)?;
+            (width as i32, height as i32)
+        } else {
+            let width = ReadLE::read_u32(&mut from)?;
+            let height = ReadLE::read_u32(&mut from)?;
+            (width as i32, height as i32)
+        };
+        if width < 0 {
+            return Err(BmpError::NegativeWidth(width));
+        }
         let planes = ReadLE::read_u16(&mut from)?;
         let bits_per_pixel = ReadLE::read_u16(&mut from)?;
-        let compression = ReadLE::read_u32(&mut from)?;
-        let _data_size = ReadLE::read_u32(&mut from)?;
-        let _hres = ReadLE::read_u32(&mut from)?;
-        let _vres = ReadLE::read_u32(&mut from)?;
-        let num_colors = ReadLE::read_u32(&mut from)?;
-        let _num_imp_colors = ReadLE::read_u32(&mut from)?;
-        let background_color = ReadLE::read_u32(&mut from)?;
-        let foreground_color = ReadLE::read_u32(&mut from)?;
-        let bg_is_zero = background_color == 0;
-        if b != B
-            || m != M
-            || pixel_offset != HEADER_SIZE
-            || dib_header != 40u32
-            || planes != 1u16
-            || bits_per_pixel != 1u16
-            || compression != 0u32
-            || num_colors != 2u32
-            || background_color == foreground_color
-        {
-            return Err(BmpError::Header);
+        let mut compression = Compression::Uncompressed;
+        let mut image_size = 0;
+        let mut num_colors = 0; // BITMAPCOREHEADER palette has always 2^bits_per_pixel colors
+        let mut resolution = None;
+        if dib_size >= 40 {
+            compression = Compression::from_u32(ReadLE::read_u32(&mut from)?);
+            image_size = ReadLE::read_u32(&mut from)?;
+            let x = ReadLE::read_u32(&mut from)?;
+            let y = ReadLE::read_u32(&mut from)?;
+            resolution = Some(Resolution { x, y });
+            num_colors = ReadLE::read_u32(&mut from)?;
+            let _num_imp_colors = ReadLE::read_u32(&mut from)?;
         }
+        let mut masks = ChannelMasks::default();
+        if dib_size >= 52 || (dib_size == 40 && compression == Compression::Bitfields) {
+            // BITMAPINFOHEADER masks follow the header
+            masks.red = ReadLE::read_u32(&mut from)?;
+            masks.green = ReadLE::read_u32(&mut from)?;
+            masks.blue = ReadLE::read_u32(&mut from)?;
+        }
+        if dib_size >= 56 {
+            masks.alpha = ReadLE::read_u32(&mut from)?;
+        }
+        let masks = if compression == Compression::Bitfields {
+            Some(masks)
+        } else {
+            None
+        };
+        let mut color_space = None;
+        let mut profile_position = None;
+        if dib_size >= 108 {
+            let cs_type = ReadLE::read_u32(&mut from)?;
+            let mut endpoints = [[0u32; 3]; 3];
+            for endpoint in endpoints.iter_mut() {
+                for coordinate in endpoint.iter_mut() {
+                    *coordinate = ReadLE::read_u32(&mut from)?;
+                }
+            }
+            let mut gamma = [0u32; 3];
+            for value in gamma.iter_mut() {
+                *value = ReadLE::read_u32(&mut from)?;
+            }
+            let mut intent = None;
+            if dib_size >= 124 {
+                intent = Some(ReadLE::read_u32(&mut from)?);
+                let profile_data = ReadLE::read_u32(&mut from)?;
+                let profile_size = ReadLE::read_u32(&mut from)?;
+                let _reserved = ReadLE::read_u32(&mut from)?;
+                if cs_type == PROFILE_EMBEDDED {
+                    profile_position = Some((profile_data, profile_size));
+                }
+            }
+            color_space = Some(ColorSpace {
+                cs_type,
+                endpoints,
+                gamma,
+                intent,
+                profile: None,
+            });
+        }
+
+        // 0 means the maximum number of colors for the bits per pixel
+        if num_colors == 0 && bits_per_pixel <= 8 {
+            num_colors = 1 << bits_per_pixel;
+        }
+        let color_size = if is_core { CORE_COLOR_SIZE } else { COLOR_SIZE };
+        let pallet_end = FILE_HEADER_SIZE
+            + dib_size
+            + masks_size(dib_header, compression)
+            + num_colors.min(MAX_COLORS) * color_size;
+        if num_colors > MAX_COLORS {
+            return Err(BmpError::InvalidPalette);
+        }
+        if pixel_offset < pallet_end {
+            return Err(BmpError::InvalidPixelOffset(pixel_offset));
+        }
+        let mut palette = Vec::with_capacity(num_colors as usize);
+        for _ in 0..num_colors {
+            let color = if is_core {
+                ReadLE::read_u24(&mut from)?
+            } else {
+                ReadLE::read_u32(&mut from)?
+            };
+            palette.push(Rgb::from_u32(color));
+        }
+
+        let row_order = if height < 0 {
+            RowOrder::TopDown
+        } else {
+            RowOrder::BottomUp
+        };
 
-        let width = u16::try_from(width)?;
-        let height = u16::try_from(height)?;
-        check_size(width, height)?;
+        Ok(BmpInfo {
+            file_size,
+            pixel_offset,
+            dib_header,
+            width: width as u32,
+            height: (height as i64).abs() as u32,
+            row_order,
+            planes,
+            bits_per_pixel,
+            compression,
+            image_size,
+            resolution,
+            palette,
+            masks,
+            color_space,
+            profile_position,
+        })
+    }
+
+    /// return the offset in the file of the end of the palette
+    fn pallet_end(&self) -> u32 {
+        let color_size = if self.dib_header == DibHeader::Core {
+            CORE_COLOR_SIZE
+        } else {
+            COLOR_SIZE
+        };
+        FILE_HEADER_SIZE
+            + self.dib_header.size()
+            + masks_size(self.dib_header, self.compression)
+            + self.palette.len() as u32 * color_size
+    }
 
+    /// skip anything between the palette and the pixel data of a BMP read with
+    /// [`BmpInfo::read`], such as the ICC profile, which is kept in the colour space
+    fn skip_to_pixels<T: Read>(&mut self, from: T) -> Result<(), BmpError> {
+        let pallet_end = self.pallet_end();
+        let gap_size = self.pixel_offset - pallet_end;
+        if gap_size > MAX_GAP_SIZE {
+            return Err(BmpError::InvalidPixelOffset(self.pixel_offset));
+        }
+        let mut gap = Vec::new();
+        from.take(gap_size as u64).read_to_end(&mut gap)?;
+        if gap.len() != gap_size as usize {
+            return Err(BmpError::Io(ErrorKind::UnexpectedEof.into()));
+        }
+        let profile = self.profile_range();
+        if let (Some(color_space), Some(profile)) = (self.color_space.as_mut(), profile) {
+            let start = profile.start - pallet_end as usize;
+            let end = profile.end - pallet_end as usize;
+            color_space.profile = Some(gap[start..end].to_vec());
+        }
+        Ok(())
+    }
+
+    /// return the position in the file of the embedded ICC profile, if it's between the palette
+    /// and the pixel data
+    fn profile_range(&self) -> Option<Range<usize>> {
+        let (profile_data, profile_size) = self.profile_position?;
+        let start = FILE_HEADER_SIZE as usize + profile_data as usize;
+        let end = start.checked_add(profile_size as usize)?;
+        if start >= self.pallet_end() as usize && end <= self.pixel_offset as usize {
+            Some(start..end)
+        } else {
+            None
+        }
+    }
+}
+
+/// return the size of the color masks following a BITMAPINFOHEADER, the other headers contain
+/// the masks
+fn masks_size(dib_header: DibHeader, compression: Compression) -> u32 {
+    if dib_header == DibHeader::Info && compression == Compression::Bitfields {
+        3 * 4
+    } else {
+        0
+    }
+}
+
+impl BmpHeader {
+    /// read the BmpHeader from read Trait `T`, leaving `from` at the start of the pixel data
+    /// the header is parsed by [`BmpInfo::read`] and must describe an uncompressed monochrome
+    /// bitmap or a BI_RLE8 or BI_RLE4 compressed one, `ink` chooses the ink palette indexes
+    /// the size is not checked against any [`crate::Limits`]
+    pub fn read<T: Read>(mut from: T, ink: InkMapping) -> Result<Self, BmpError> {
+        let mut info = BmpInfo::read(&mut from)?;
+        let header = BmpHeader::from_info(&info, ink)?;
+        info.skip_to_pixels(&mut from)?;
         Ok(BmpHeader {
-            height,
-            width,
-            bg_is_zero,
+            color_space: info.color_space,
+            ..header
+        })
+    }
+
+    /// return the BmpHeader of the bitmap described by `info` like [`BmpHeader::read`], without
+    /// the ICC profile which is read by [`BmpInfo::skip_to_pixels`]
+    fn from_info(info: &BmpInfo, ink: InkMapping) -> Result<Self, BmpError> {
+        if info.planes != 1u16 {
+            return Err(BmpError::InvalidPlanes(info.planes));
+        }
+        let mut dark_indexes = vec![];
+        let colors = match (info.bits_per_pixel, info.compression) {
+            (1, Compression::Uncompressed) => {
+                if info.palette.len() < 2 || info.palette[0] == info.palette[1] {
+                    return Err(BmpError::InvalidPalette);
+                }
+                [info.palette[0], info.palette[1]]
+            }
+            (8, Compression::Rle8) | (4, Compression::Rle4) if ink != InkMapping::Luminance => {
+                let palette = &info.palette;
+                if palette.len() < 2 || palette[0] == palette[1] {
+                    return Err(BmpError::InvalidPalette);
+                }
+                let ink_index = if ink == InkMapping::Zero { 0 } else { 1 };
+                dark_indexes = (0..palette.len()).map(|i| i == ink_index).collect();
+                [palette[1 - ink_index], palette[ink_index]]
+            }
+            (8, Compression::Rle8) | (4, Compression::Rle4) => {
+                let lightest = info.palette.iter().max_by_key(|c| c.luminance());
+                let darkest = info.palette.iter().min_by_key(|c| c.luminance());
+                let (lightest, darkest) = match (lightest, darkest) {
+                    (Some(lightest), Some(darkest)) => (*lightest, *darkest),
+                    _ => return Err(BmpError::InvalidPalette),
+                };
+                let midpoint = lightest.luminance() + darkest.luminance();
+                dark_indexes = info
+                    .palette
+                    .iter()
+                    .map(|c| c.luminance() * 2 < midpoint)
+                    .collect();
+                if lightest.luminance() == darkest.luminance() {
+                    // every pixel is light, the colors must differ anyway
+                    [Rgb::WHITE, Rgb::BLACK]
+                } else {
+                    [lightest, darkest]
+                }
+            }
+            (1, compression) => return Err(BmpError::UnsupportedCompression(compression)),
+            (bits_per_pixel, _) => return Err(BmpError::UnsupportedBitsPerPixel(bits_per_pixel)),
+        };
+
+        Ok(BmpHeader {
+            height: info.height,
+            width: info.width,
+            colors,
+            // the decoded RLE rows have the bit 1 set for the ink indexes
+            bg_is_zero: info.compression == Compression::Uncompressed && ink.zero_is_ink(&colors),
+            row_order: info.row_order,
+            resolution: info.resolution,
+            color_space: info.color_space.clone(),
+            compression: info.compression,
+            dark_indexes,
+            file_size: info.file_size,
+            image_size: info.image_size,
+            pixel_offset: info.pixel_offset,
         })
     }
 }
@@ -92,6 +878,12 @@ impl<R: Read> ReadLE for R {
         Ok(u32::from_le_bytes(buffer))
     }
 
+    fn read_u24(&mut self) -> Result<u32, BmpError> {
+        let mut buffer = [0u8; 4];
+        self.read_exact(&mut buffer[..3])?;
+        Ok(u32::from_le_bytes(buffer))
+    }
+
     fn read_u16(&mut self) -> Result<u16, BmpError> {
         let mut buffer = [0u8; 2];
         self.read_exact(&mut buffer)?;
@@ -108,6 +900,8 @@ impl<R: Read> ReadLE for R {
 trait ReadLE {
     /// Read a 32-bit uint
     fn read_u32(&mut self) -> Result<u32, BmpError>;
+    /// Read a 24-bit uint
+    fn read_u24(&mut self) -> Result<u32, BmpError>;
     /// Read a 16-bit uint
     fn read_u16(&mut self) -> Result<u16, BmpError>;
     /// Read a 8-bit uint
@@ -116,8 +910,13 @@ trait ReadLE {
 
 #[cfg(test)]
 mod test {
+    use crate::bit::{self, BitStreamReader};
     use crate::decode::ReadLE;
-    use crate::BmpHeader;
+    use crate::{
+        Bmp, BmpError, BmpHeader, BmpInfo, BmpRef, Compression, DecodeMode, DecodeWarning,
+        DibHeader, InkMapping, Limits, Palette, ReadOptions, Resolution, Rgb, RowDecoder,
+        RowEncoder, RowOrder, Threshold, WriteOptions,
+    };
     use std::fs::File;
     use std::io::Cursor;
 
@@ -128,18 +927,847 @@ mod test {
         assert_eq!(1, ReadLE::read_u8(&mut cursor).unwrap());
         assert_eq!(1, ReadLE::read_u16(&mut cursor).unwrap());
         assert_eq!(1, ReadLE::read_u32(&mut cursor).unwrap());
+
+        let mut cursor = Cursor::new(vec![1u8, 2, 3]);
+        assert_eq!(0x03_02_01, ReadLE::read_u24(&mut cursor).unwrap());
     }
 
     #[test]
     fn test_header() {
         let file = File::open("test_bmp/monochrome_image.bmp").unwrap();
-        let bmp_header = BmpHeader::read(file).unwrap();
+        let bmp_header = BmpHeader::read(file, InkMapping::default()).unwrap();
         assert_eq!(18, bmp_header.width);
         assert_eq!(18, bmp_header.height);
 
         let file = File::open("test_bmp/test1.bmp").unwrap();
-        let bmp_header = BmpHeader::read(file).unwrap();
+        let bmp_header = BmpHeader::read(file, InkMapping::default()).unwrap();
         assert_eq!(2, bmp_header.width);
         assert_eq!(2, bmp_header.height);
+        assert_eq!(Some(Resolution { x: 512, y: 512 }), bmp_header.resolution);
+    }
+
+    #[test]
+    fn test_read_matches_bit_reader() {
+        for name in &[
+            "monochrome_image",
+            "qr-bolt11",
+            "test1",
+            "test2",
+            "qr_normalized",
+        ] {
+            let path = format!("test_bmp/{}.bmp", name);
+            let mut file = File::open(&path).unwrap();
+            let header = BmpHeader::read(&mut file, InkMapping::default()).unwrap();
+            let width = header.width as u8;
+            let mut reader = BitStreamReader::new(&mut file);
+            let mut rows = vec![];
+            for _ in 0..header.height {
+                let row: Vec<bool> = (0..header.width)
+                    .map(|_| (reader.read(1).unwrap() == 1) != header.bg_is_zero())
+                    .collect();
+                reader.read((8 - width % 8) % 8).unwrap();
+                reader.read(header.padding() as u8 * 8).unwrap();
+                rows.push(row);
+            }
+            rows.reverse();
+
+            let bmp = Bmp::read(File::open(&path).unwrap()).unwrap();
+            assert_eq!(Bmp::new(rows).unwrap(), bmp, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_top_down() {
+        let mut bytes = Bmp::read(File::open("test_bmp/test1.bmp").unwrap())
+            .unwrap()
+            .to_bytes();
+        // negate the height and swap the two rows
+        bytes[22..26].copy_from_slice(&(-2i32).to_le_bytes());
+        let (first, second) = bytes[62..].split_at_mut(4);
+        first.swap_with_slice(second);
+
+        let header = BmpHeader::read(&bytes[..], InkMapping::default()).unwrap();
+        assert_eq!(2, header.height);
+        assert_eq!(RowOrder::TopDown, header.row_order);
+
+        let bmp = Bmp::read(&bytes[..]).unwrap();
+        assert_eq!(
+            Bmp::read(File::open("test_bmp/test1.bmp").unwrap()).unwrap(),
+            bmp
+        );
+    }
+
+    #[test]
+    fn test_row_decoder() {
+        let bmp = Bmp::read(File::open("test_bmp/qr_not_normalized.bmp").unwrap()).unwrap();
+        let file = File::open("test_bmp/qr_not_normalized.bmp").unwrap();
+        let mut decoder = RowDecoder::new(file).unwrap();
+        assert_eq!(
+            (bmp.width(), bmp.height()),
+            (decoder.width(), decoder.height())
+        );
+        assert_eq!(RowOrder::BottomUp, decoder.row_order());
+        assert_eq!(bmp.palette(), Some(decoder.palette()));
+        let mut i = bmp.height();
+        while let Some(row) = decoder.next_row_pixels().unwrap() {
+            i -= 1;
+            assert_eq!(row.len(), bmp.width() as usize);
+            for (j, pixel) in row.iter().enumerate() {
+                assert_eq!(*pixel, bmp.get(i, j as u32));
+            }
+        }
+        assert_eq!(0, i);
+        assert!(decoder.next_row().unwrap().is_none());
+
+        let options = WriteOptions {
+            row_order: RowOrder::TopDown,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        bmp.write_with(&mut bytes, &options).unwrap();
+        let mut decoder = RowDecoder::new(&bytes[..]).unwrap();
+        assert_eq!(RowOrder::TopDown, decoder.row_order());
+        let first = decoder.next_row().unwrap().unwrap();
+        assert_eq!(first.len(), (bmp.width() as usize + 7) / 8);
+        assert_eq!(first[0] & 0x80 != 0, bmp.get(0, 0));
+        // padding bits past the width are zero
+        assert_eq!(
+            first.last().unwrap() & !bit::last_byte_mask(bmp.width() as usize),
+            0
+        );
+
+        // the rows after the header are truncated
+        let mut decoder = RowDecoder::new(&bytes[..bytes.len() - 1]).unwrap();
+        for _ in 1..bmp.height() {
+            decoder.next_row().unwrap();
+        }
+        assert!(matches!(
+            decoder.next_row(),
+            Err(BmpError::TruncatedData { .. })
+        ));
+
+        // a receipt taller than the default limits of a Bmp
+        let mut encoder = RowEncoder::new(vec![], 576, 20_000).unwrap();
+        let row = [0xAAu8; 72];
+        for _ in 0..20_000 {
+            encoder.write_row(&row).unwrap();
+        }
+        let bytes = encoder.finish().unwrap();
+        assert!(matches!(
+            Bmp::read(&bytes[..]),
+            Err(BmpError::Size(576, 20_000))
+        ));
+        let mut decoder = RowDecoder::new(&bytes[..]).unwrap();
+        let mut rows = 0;
+        while let Some(read) = decoder.next_row().unwrap() {
+            assert_eq!(&row[..], read);
+            rows += 1;
+        }
+        assert_eq!(20_000, rows);
+        let limits = Limits {
+            max_width: 500,
+            ..Default::default()
+        };
+        let options = ReadOptions {
+            limits,
+            ..Default::default()
+        };
+        assert!(matches!(
+            RowDecoder::with_options(&bytes[..], &options),
+            Err(BmpError::Size(576, 20_000))
+        ));
+    }
+
+    #[test]
+    fn test_bmp_ref() {
+        for name in &[
+            "qr_not_normalized.bmp",
+            "monochrome_image_v5.bmp",
+            "test1.bmp",
+        ] {
+            let bytes = std::fs::read(format!("test_bmp/{}", name)).unwrap();
+            let bmp = Bmp::read(&bytes[..]).unwrap();
+            let view = BmpRef::new(&bytes).unwrap();
+            assert_eq!((bmp.width(), bmp.height()), (view.width(), view.height()));
+            assert_eq!(bmp.palette(), Some(view.palette()));
+            assert_eq!(bmp.resolution(), view.resolution());
+            let mut rows = 0;
+            for (i, row) in view.rows().enumerate() {
+                for (j, pixel) in row.enumerate() {
+                    assert_eq!(pixel, bmp.get(i as u32, j as u32));
+                    assert_eq!(pixel, view.get(i as u32, j as u32));
+                }
+                rows += 1;
+            }
+            assert_eq!(rows, bmp.height());
+            assert_eq!(bmp, view.to_bmp());
+            assert_eq!(bmp.color_space(), view.to_bmp().color_space());
+        }
+
+        let bmp = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        let options = WriteOptions {
+            row_order: RowOrder::TopDown,
+            foreground: Rgb::WHITE,
+            background: Rgb::BLACK,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        bmp.write_with(&mut bytes, &options).unwrap();
+        let read_options = ReadOptions {
+            ink: InkMapping::One,
+            ..Default::default()
+        };
+        let view = BmpRef::with_options(&bytes, &read_options).unwrap();
+        assert_eq!(bmp, view.to_bmp());
+        assert_eq!(bmp.get(0, 1), view.rows().next().unwrap().nth(1).unwrap());
+
+        assert!(matches!(
+            BmpRef::new(&bytes[..bytes.len() - 1]),
+            Err(BmpError::TruncatedData { row: 17, .. })
+        ));
+
+        // the area limits don't apply as the pixels are borrowed
+        let mut encoder = RowEncoder::new(vec![], 576, 20_000).unwrap();
+        for i in 0..20_000 {
+            encoder.write_row(&[i as u8; 72]).unwrap();
+        }
+        let bytes = encoder.finish().unwrap();
+        let view = BmpRef::new(&bytes).unwrap();
+        assert_eq!((576, 20_000), (view.width(), view.height()));
+        // bottom-up rows, the last written row is 19_999 % 256 = 0b0001_1111
+        assert!(!view.get(0, 2) && view.get(0, 3) && view.get(19_998, 7));
+        assert!(view.rows().last().unwrap().all(|pixel| !pixel));
+        assert_eq!(20_000, view.rows().count());
+    }
+
+    /// return a BMP with BITMAPINFOHEADER and the pixels of `bmp` encoded with `bits_per_pixel`
+    /// bits whose value is returned by `value`, `extra` are the masks and the palette
+    fn encode_pixels(
+        bmp: &Bmp,
+        bits_per_pixel: u16,
+        compression: u32,
+        extra: &[u8],
+        value: impl Fn(bool, u32) -> u32,
+    ) -> Vec<u8> {
+        let mut pixels = vec![];
+        for i in (0..bmp.height()).rev() {
+            let mut row = vec![];
+            for j in 0..bmp.width() {
+                let value = value(bmp.get(i, j), j);
+                match bits_per_pixel {
+                    4 if j % 2 == 0 => row.push((value << 4) as u8),
+                    4 => *row.last_mut().unwrap() |= value as u8,
+                    bits => row.extend_from_slice(&value.to_le_bytes()[..bits as usize / 8]),
+                }
+            }
+            while row.len() % 4 != 0 {
+                row.push(0);
+            }
+            pixels.extend(row);
+        }
+        encode_info_header(
+            bmp.width(),
+            bmp.height(),
+            bits_per_pixel,
+            compression,
+            extra,
+            &pixels,
+        )
+    }
+
+    /// return a BMP with BITMAPINFOHEADER followed by `extra`, the masks and the palette, and the
+    /// pixel data `pixels`
+    fn encode_info_header(
+        width: u32,
+        height: u32,
+        bits_per_pixel: u16,
+        compression: u32,
+        extra: &[u8],
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let offset = 54 + extra.len() as u32;
+        let mut bytes = vec![b'B', b'M'];
+        bytes.extend_from_slice(&(offset + pixels.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        bytes.extend_from_slice(&40u32.to_le_bytes());
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&bits_per_pixel.to_le_bytes());
+        bytes.extend_from_slice(&compression.to_le_bytes());
+        bytes.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 8]);
+        let num_colors = if bits_per_pixel <= 8 {
+            extra.len() / 4
+        } else {
+            0
+        };
+        bytes.extend_from_slice(&(num_colors as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(extra);
+        bytes.extend_from_slice(pixels);
+        bytes
+    }
+
+    #[test]
+    fn test_read_thresholded() {
+        let bmp = Bmp::read(File::open("test_bmp/qr_not_normalized.bmp").unwrap()).unwrap();
+        let file = File::open("test_bmp/qr_not_normalized.bmp").unwrap();
+        assert_eq!(bmp, Bmp::read_thresholded(file, Threshold::Otsu).unwrap());
+
+        // dark and light pixels with some noise
+        let gray = |dark: bool, j: u32| if dark { 20 + j % 30 } else { 200 + j % 40 };
+        let bytes = encode_pixels(&bmp, 24, 0, &[], |dark, j| gray(dark, j) * 0x01_01_01);
+        for threshold in &[Threshold::Fixed(128), Threshold::Otsu] {
+            assert_eq!(bmp, Bmp::read_thresholded(&bytes[..], *threshold).unwrap());
+        }
+        let blank = vec![vec![false; bmp.width() as usize]; bmp.height() as usize];
+        let blank = Bmp::new(blank).unwrap();
+        assert_eq!(
+            blank,
+            Bmp::read_thresholded(&bytes[..], Threshold::Fixed(0)).unwrap()
+        );
+        assert_eq!(
+            blank.inverse(),
+            Bmp::read_thresholded(&bytes[..], Threshold::Fixed(255)).unwrap()
+        );
+
+        let bytes = encode_pixels(&bmp, 32, 0, &[], |dark, j| {
+            0xFF00_0000 | (gray(dark, j) * 0x01_01_01)
+        });
+        assert_eq!(
+            bmp,
+            Bmp::read_thresholded(&bytes[..], Threshold::Otsu).unwrap()
+        );
+
+        // 5-6-5 masks following the header
+        let mut masks = vec![];
+        for mask in &[0xF800u32, 0x07E0, 0x001F] {
+            masks.extend_from_slice(&mask.to_le_bytes());
+        }
+        let bytes = encode_pixels(
+            &bmp,
+            16,
+            3,
+            &masks,
+            |dark, _| if dark { 0x0841 } else { 0xFFFF },
+        );
+        let info = BmpInfo::read(&bytes[..]).unwrap();
+        assert_eq!(Compression::Bitfields, info.compression);
+        assert_eq!(0x07E0, info.masks.unwrap().green);
+        assert_eq!(
+            bmp,
+            Bmp::read_thresholded(&bytes[..], Threshold::Otsu).unwrap()
+        );
+
+        let mut palette = vec![];
+        for i in 0..16u32 {
+            palette.extend_from_slice(&(i * 17 * 0x01_01_01).to_le_bytes());
+        }
+        let bytes = encode_pixels(&bmp, 4, 0, &palette, |dark, _| if dark { 3 } else { 14 });
+        assert_eq!(
+            bmp,
+            Bmp::read_thresholded(&bytes[..], Threshold::Otsu).unwrap()
+        );
+        assert_eq!(
+            blank,
+            Bmp::read_thresholded(&bytes[..], Threshold::Fixed(50)).unwrap()
+        );
+
+        let bytes = encode_pixels(&bmp, 8, 0, &palette, |dark, _| if dark { 16 } else { 1 });
+        assert!(matches!(
+            Bmp::read_thresholded(&bytes[..], Threshold::Otsu),
+            Err(BmpError::InvalidPalette)
+        ));
+        let bytes = encode_pixels(&bmp, 8, 0, &palette, |dark, _| if dark { 0 } else { 15 });
+        assert_eq!(
+            bmp,
+            Bmp::read_thresholded(&bytes[..], Threshold::Otsu).unwrap()
+        );
+        assert!(matches!(
+            Bmp::read_thresholded(&bytes[..bytes.len() - 1], Threshold::Otsu),
+            Err(BmpError::TruncatedData { row: 86, .. })
+        ));
+        assert!(matches!(
+            Bmp::read_thresholded(&bytes[..bytes.len() - 1], Threshold::Fixed(128)),
+            Err(BmpError::TruncatedData { row: 86, .. })
+        ));
+        let limits = Limits {
+            max_pixels: 80 * 80,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Bmp::read_thresholded_with_limits(&bytes[..], Threshold::Otsu, &limits),
+            Err(BmpError::Size(87, 87))
+        ));
+        let limits = Limits {
+            max_pixels: 87 * 87,
+            ..Default::default()
+        };
+        assert_eq!(
+            bmp,
+            Bmp::read_thresholded_with_limits(&bytes[..], Threshold::Fixed(128), &limits).unwrap()
+        );
+
+        let bytes = encode_pixels(&bmp, 24, 1, &[], |_, _| 0);
+        assert!(matches!(
+            Bmp::read_thresholded(&bytes[..], Threshold::Otsu),
+            Err(BmpError::UnsupportedCompression(Compression::Rle8))
+        ));
+        let mut bytes = encode_pixels(&bmp, 24, 0, &[], |_, _| 0);
+        bytes[28..30].copy_from_slice(&64u16.to_le_bytes());
+        assert!(matches!(
+            Bmp::read_thresholded(&bytes[..], Threshold::Otsu),
+            Err(BmpError::UnsupportedBitsPerPixel(64))
+        ));
+    }
+
+    /// return a bitmap with BITMAPINFOHEADER, black and white palette and run length encoded
+    /// `data`
+    fn encode_rle(width: u32, height: u32, compression: u32, data: &[u8]) -> Vec<u8> {
+        let bits_per_pixel: u16 = if compression == 1 { 8 } else { 4 };
+        let palette = [0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0];
+        encode_info_header(width, height, bits_per_pixel, compression, &palette, data)
+    }
+
+    #[test]
+    fn test_rle() {
+        let expected = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        for name in &["monochrome_image_rle8.bmp", "monochrome_image_rle4.bmp"] {
+            let bytes = std::fs::read(format!("test_bmp/{}", name)).unwrap();
+            let info = BmpInfo::read(&bytes[..]).unwrap();
+            assert_eq!(4, info.palette.len());
+            let bmp = Bmp::read(&bytes[..]).unwrap();
+            assert_eq!(expected, bmp);
+            let palette = Palette {
+                background: Rgb::WHITE,
+                foreground: Rgb::BLACK,
+            };
+            assert_eq!(Some(palette), bmp.palette());
+            assert!(matches!(
+                BmpRef::new(&bytes),
+                Err(BmpError::UnsupportedCompression(_))
+            ));
+        }
+
+        let data = [
+            3, 1, 0, 0, // 3 dark pixels, end of line
+            0, 2, 1, 1, // delta, 1 right and 1 down
+            2, 1, 10, 1, // 2 dark pixels, 10 dark pixels exceeding the width
+            0, 1, // end of bitmap
+        ];
+        let expected = Bmp::new(vec![
+            vec![false, true, true, true],
+            vec![false; 4],
+            vec![true, true, true, false],
+        ])
+        .unwrap();
+        let bytes = encode_rle(4, 3, 1, &data);
+        assert_eq!(expected, Bmp::read(&bytes[..]).unwrap());
+        let mut decoder = RowDecoder::new(&bytes[..]).unwrap();
+        assert_eq!(&[0xE0], decoder.next_row().unwrap().unwrap());
+        assert_eq!(&[0x00], decoder.next_row().unwrap().unwrap());
+        assert_eq!(&[0x70], decoder.next_row().unwrap().unwrap());
+        assert!(decoder.next_row().unwrap().is_none());
+
+        // the ink mapping chooses the palette indexes of the ink
+        let palette = [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0x40, 0x40, 0x40, 0];
+        let bytes = encode_info_header(4, 1, 8, 1, &palette, &[1, 0, 1, 1, 2, 2, 0, 1]);
+        let options = |ink| ReadOptions {
+            ink,
+            ..Default::default()
+        };
+        let read = |ink| Bmp::read_with(&bytes[..], &options(ink)).unwrap();
+        let row = |pixels: Vec<bool>| Bmp::new(vec![pixels]).unwrap();
+        assert_eq!(
+            row(vec![true, false, true, true]),
+            read(InkMapping::Luminance)
+        );
+        assert_eq!(row(vec![true, false, false, false]), read(InkMapping::Zero));
+        assert_eq!(row(vec![false, true, false, false]), read(InkMapping::One));
+        let palette = Palette {
+            background: Rgb::WHITE,
+            foreground: Rgb::BLACK,
+        };
+        assert_eq!(Some(palette), read(InkMapping::Zero).palette());
+        let bytes = encode_info_header(4, 1, 8, 1, &[0, 0, 0, 0, 0, 0, 0, 0], &[4, 0, 0, 1]);
+        assert!(matches!(
+            Bmp::read_with(&bytes[..], &options(InkMapping::One)),
+            Err(BmpError::InvalidPalette)
+        ));
+
+        // the end of bitmap leaves the remaining rows light
+        let bytes = encode_rle(4, 3, 1, &[1, 1, 0, 1]);
+        let mut expected = vec![vec![false; 4]; 3];
+        expected[2][0] = true;
+        let expected = Bmp::new(expected).unwrap();
+        assert_eq!(expected, Bmp::read(&bytes[..]).unwrap());
+
+        // absolute mode with an index outside the palette, and alternating indexes
+        let bytes = encode_rle(4, 2, 2, &[0, 4, 0x19, 0x01, 0, 0, 4, 0x10, 0, 1]);
+        let expected = Bmp::new(vec![
+            vec![true, false, true, false],
+            vec![true, false, false, true],
+        ])
+        .unwrap();
+        assert_eq!(expected, Bmp::read(&bytes[..]).unwrap());
+
+        // delta past the last row
+        let bytes = encode_rle(4, 2, 1, &[0, 2, 0, 200, 1, 1]);
+        assert_eq!(
+            Bmp::new(vec![vec![false; 4]; 2]).unwrap(),
+            Bmp::read(&bytes[..]).unwrap()
+        );
+
+        let bytes = encode_rle(4, 3, 1, &data[..10]);
+        assert!(matches!(
+            Bmp::read(&bytes[..]),
+            Err(BmpError::TruncatedData { row: 2, .. })
+        ));
+        let bytes = encode_rle(4, 3, 1, &[0, 5, 1, 1]);
+        assert!(matches!(
+            Bmp::read(&bytes[..]),
+            Err(BmpError::TruncatedData { row: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_lenient() {
+        let bmp = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        let bytes = bmp.to_bytes();
+        let lenient = ReadOptions {
+            mode: DecodeMode::Lenient,
+            ..Default::default()
+        };
+        let (read, warnings) = Bmp::read_with_warnings(&bytes[..], &lenient).unwrap();
+        assert_eq!(bmp, read);
+        assert!(warnings.is_empty());
+
+        // the upper row is missing and the row below lacks the padding
+        let truncated = &bytes[..bytes.len() - 5];
+        let (read, warnings) = Bmp::read_with_warnings(truncated, &lenient).unwrap();
+        assert_eq!(vec![DecodeWarning::TruncatedData { row: 16 }], warnings);
+        let mut expected = bmp.clone();
+        for j in 0..bmp.width() as usize {
+            bit::set_bit(expected.row_mut(0), j, false);
+        }
+        assert_eq!(expected, read);
+        assert!(matches!(
+            Bmp::read(truncated),
+            Err(BmpError::TruncatedData { row: 16, .. })
+        ));
+
+        // the missing rows are background even when the bit 0 is the dark one
+        let options = ReadOptions {
+            ink: InkMapping::One,
+            ..lenient.clone()
+        };
+        let (read, _) = Bmp::read_with_warnings(&bytes[..bytes.len() - 20], &options).unwrap();
+        assert!((0..bmp.width()).all(|j| !read.get(0, j) && !read.get(4, j)));
+        assert!((0..bmp.width()).any(|j| read.get(5, j)));
+
+        let mut wrong_sizes = bytes.clone();
+        wrong_sizes[2..6].copy_from_slice(&100u32.to_le_bytes());
+        assert!(matches!(
+            Bmp::read(&wrong_sizes[..]),
+            Err(BmpError::InvalidFileSize(100))
+        ));
+        wrong_sizes[34..38].copy_from_slice(&5u32.to_le_bytes());
+        assert!(matches!(
+            BmpRef::new(&wrong_sizes),
+            Err(BmpError::InvalidImageSize(5))
+        ));
+        let mut decoder = RowDecoder::with_options(&wrong_sizes[..], &lenient).unwrap();
+        let expected = [
+            DecodeWarning::InvalidImageSize(5),
+            DecodeWarning::InvalidFileSize(100),
+        ];
+        assert_eq!(&expected, decoder.warnings());
+        while decoder.next_row().unwrap().is_some() {}
+        assert_eq!(&expected, decoder.warnings());
+        assert!(BmpRef::with_options(&wrong_sizes, &lenient).is_ok());
+
+        // over-reported sizes are accepted
+        let mut bytes = std::fs::read("test_bmp/test1.bmp").unwrap();
+        let image_size = u32::from_le_bytes([bytes[34], bytes[35], bytes[36], bytes[37]]);
+        bytes[34..38].copy_from_slice(&(image_size + 2).to_le_bytes());
+        let expected = Bmp::read(File::open("test_bmp/test1.bmp").unwrap()).unwrap();
+        assert_eq!(expected, Bmp::read(&bytes[..]).unwrap());
+        let (_, warnings) = Bmp::read_with_warnings(&bytes[..], &lenient).unwrap();
+        assert!(warnings.is_empty());
+
+        // run length encoded
+        let data = [3, 1, 0, 0, 0, 2, 1, 1, 2, 1];
+        let bytes = encode_rle(4, 3, 1, &data);
+        assert!(Bmp::read(&bytes[..]).is_err());
+        let (read, warnings) = Bmp::read_with_warnings(&bytes[..], &lenient).unwrap();
+        assert_eq!(vec![DecodeWarning::TruncatedData { row: 2 }], warnings);
+        let expected = Bmp::new(vec![
+            vec![false, true, true, false],
+            vec![false; 4],
+            vec![true, true, true, false],
+        ])
+        .unwrap();
+        assert_eq!(expected, read);
+    }
+
+    #[test]
+    fn test_v4_v5_header() {
+        let expected = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        assert!(expected.color_space().is_none());
+
+        let v4 = Bmp::read(File::open("test_bmp/monochrome_image_v4.bmp").unwrap()).unwrap();
+        assert_eq!(expected, v4);
+        let color_space = v4.color_space().unwrap();
+        assert_eq!(color_space.cs_type, 0x7352_4742);
+        assert_eq!(color_space.endpoints, [[1, 2, 3], [4, 5, 6], [7, 8, 9]]);
+        assert_eq!(color_space.gamma, [0x10000, 0x20000, 0x30000]);
+        assert_eq!(color_space.intent, None);
+        assert_eq!(color_space.profile, None);
+
+        let v5 = Bmp::read(File::open("test_bmp/monochrome_image_v5.bmp").unwrap()).unwrap();
+        assert_eq!(expected, v5);
+        let color_space = v5.color_space().unwrap();
+        assert_eq!(color_space.cs_type, 0x4D42_4544);
+        assert_eq!(color_space.intent, Some(4));
+        assert_eq!(
+            color_space.profile.as_deref(),
+            Some(&b"fake icc profile"[..])
+        );
+        assert_eq!(v5.mul(2).unwrap().color_space(), Some(color_space));
+
+        // the profile placed after the pixel data, as recommended, is not read
+        let bytes = std::fs::read("test_bmp/monochrome_image_v5.bmp").unwrap();
+        let mut moved = bytes[..146].to_vec();
+        moved.extend_from_slice(&bytes[162..]);
+        moved.extend_from_slice(&bytes[146..162]);
+        moved[10..14].copy_from_slice(&146u32.to_le_bytes());
+        moved[126..130].copy_from_slice(&(146u32 + 72 - 14).to_le_bytes());
+        let v5 = Bmp::read(&moved[..]).unwrap();
+        assert_eq!(expected, v5);
+        assert_eq!(v5.color_space().unwrap().profile, None);
+
+        // trailing bytes counted in the file size
+        let mut bytes = std::fs::read("test_bmp/monochrome_image.bmp").unwrap();
+        let file_size = bytes.len() as u32 + 2;
+        bytes[2..6].copy_from_slice(&file_size.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        assert_eq!(expected, Bmp::read(&bytes[..]).unwrap());
+        assert_eq!(expected, BmpRef::new(&bytes).unwrap().to_bmp());
+    }
+
+    #[test]
+    fn test_core_header() {
+        let expected = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        let file = File::open("test_bmp/monochrome_image_core.bmp").unwrap();
+        let core = Bmp::read(file).unwrap();
+        assert_eq!(expected, core);
+        assert!(core.color_space().is_none());
+        assert!(core.resolution().is_none());
+    }
+
+    #[test]
+    fn test_pixel_offset() {
+        let expected = Bmp::read(File::open("test_bmp/test1.bmp").unwrap()).unwrap();
+        let bytes = expected.to_bytes();
+
+        let mut no_colors = bytes.clone();
+        no_colors[46..50].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(expected, Bmp::read(&no_colors[..]).unwrap());
+
+        // 2 unused palette colors and 8 bytes of garbage
+        let mut gap = bytes[..62].to_vec();
+        gap[2..6].copy_from_slice(&86u32.to_le_bytes());
+        gap[10..14].copy_from_slice(&78u32.to_le_bytes());
+        gap[46..50].copy_from_slice(&4u32.to_le_bytes());
+        gap.extend_from_slice(&[0xAA; 16]);
+        gap.extend_from_slice(&bytes[62..]);
+        assert_eq!(expected, Bmp::read(&gap[..]).unwrap());
+
+        // palette doesn't fit before the pixel data
+        gap[46..50].copy_from_slice(&7u32.to_le_bytes());
+        assert!(Bmp::read(&gap[..]).is_err());
+
+        let mut inside_header = bytes.clone();
+        inside_header[10..14].copy_from_slice(&58u32.to_le_bytes());
+        assert!(Bmp::read(&inside_header[..]).is_err());
+
+        let mut too_far = bytes;
+        too_far[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Bmp::read(&too_far[..]).is_err());
+    }
+
+    #[test]
+    fn test_palette_luminance() {
+        let expected = Bmp::read(File::open("test_bmp/test1.bmp").unwrap()).unwrap();
+        let palette = expected.palette().unwrap();
+        assert_eq!(palette.background, Rgb::WHITE);
+        assert_eq!(palette.foreground, Rgb::BLACK);
+        let bytes = expected.to_bytes();
+
+        let dark_blue = Rgb { r: 0, g: 0, b: 128 };
+        let yellow = Rgb {
+            r: 255,
+            g: 255,
+            b: 0,
+        };
+        let mut blue_yellow = bytes.clone();
+        blue_yellow[54..58].copy_from_slice(&dark_blue.to_u32().to_le_bytes());
+        blue_yellow[58..62].copy_from_slice(&yellow.to_u32().to_le_bytes());
+        let bmp = Bmp::read(&blue_yellow[..]).unwrap();
+        assert_eq!(expected.inverse(), bmp);
+        let expected_palette = Palette {
+            background: yellow,
+            foreground: dark_blue,
+        };
+        assert_eq!(Some(expected_palette), bmp.palette());
+
+        let options = ReadOptions {
+            ink: InkMapping::One,
+            ..Default::default()
+        };
+        let bmp = Bmp::read_with(&blue_yellow[..], &options).unwrap();
+        assert_eq!(expected, bmp);
+        let expected_palette = Palette {
+            background: dark_blue,
+            foreground: yellow,
+        };
+        assert_eq!(Some(expected_palette), bmp.palette());
+
+        let light_grey = Rgb {
+            r: 200,
+            g: 200,
+            b: 200,
+        };
+        let mut white_grey = bytes;
+        white_grey[58..62].copy_from_slice(&light_grey.to_u32().to_le_bytes());
+        assert_eq!(expected, Bmp::read(&white_grey[..]).unwrap());
+        let options = ReadOptions {
+            ink: InkMapping::Zero,
+            ..Default::default()
+        };
+        assert_eq!(
+            expected.inverse(),
+            Bmp::read_with(&white_grey[..], &options).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_bmp_info() {
+        let info = BmpInfo::read(File::open("test_bmp/monochrome_image_v5.bmp").unwrap()).unwrap();
+        assert_eq!(info.dib_header, DibHeader::V5);
+        assert_eq!((info.width, info.height), (18, 18));
+        assert_eq!(info.row_order, RowOrder::BottomUp);
+        assert_eq!(info.bits_per_pixel, 1);
+        assert_eq!(info.compression, Compression::Uncompressed);
+        assert_eq!(info.palette, vec![Rgb::BLACK, Rgb::WHITE]);
+        assert_eq!(info.resolution, Some(Resolution { x: 2835, y: 2835 }));
+        assert_eq!(info.pixel_offset, 162);
+        assert_eq!(info.file_size, 234);
+        assert_eq!(info.image_size, 72);
+        assert_eq!(info.color_space.unwrap().cs_type, 0x4D42_4544);
+
+        let info =
+            BmpInfo::read(File::open("test_bmp/monochrome_image_core.bmp").unwrap()).unwrap();
+        assert_eq!(info.dib_header, DibHeader::Core);
+        assert_eq!(info.resolution, None);
+        assert_eq!(info.palette.len(), 2);
+
+        // 24 bits per pixel, 100_000 x 100_000 top-down image, unsupported by Bmp::read
+        let mut bytes = Bmp::read(File::open("test_bmp/test1.bmp").unwrap())
+            .unwrap()
+            .to_bytes()[..54]
+            .to_vec();
+        bytes[10..14].copy_from_slice(&54u32.to_le_bytes());
+        bytes[18..22].copy_from_slice(&100_000u32.to_le_bytes());
+        bytes[22..26].copy_from_slice(&(-100_000i32).to_le_bytes());
+        bytes[28..30].copy_from_slice(&24u16.to_le_bytes());
+        bytes[46..50].copy_from_slice(&0u32.to_le_bytes());
+        let info = BmpInfo::read(&bytes[..]).unwrap();
+        assert_eq!((info.width, info.height), (100_000, 100_000));
+        assert_eq!(info.row_order, RowOrder::TopDown);
+        assert_eq!(info.bits_per_pixel, 24);
+        assert!(info.palette.is_empty());
+        assert!(Bmp::read(&bytes[..]).is_err());
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            BmpInfo::read(&bytes[..]),
+            Err(BmpError::BadMagic([b'X', b'M']))
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        use std::error::Error;
+
+        let bytes = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap())
+            .unwrap()
+            .to_bytes();
+        let err = Bmp::read(&bytes[..bytes.len() - 5]).unwrap_err();
+        assert!(matches!(err, BmpError::TruncatedData { row: 16, .. }));
+        assert!(err.source().is_some());
+        assert_eq!(
+            err.to_string(),
+            "pixel data truncated at row 16: failed to fill whole buffer"
+        );
+
+        let err = Bmp::read(&bytes[..20]).unwrap_err();
+        assert!(matches!(err, BmpError::Io(_)));
+        assert!(err.source().is_some());
+
+        let mut unsupported = bytes.clone();
+        unsupported[14] = 64;
+        let err = Bmp::read(&unsupported[..]).unwrap_err();
+        assert!(matches!(err, BmpError::UnsupportedDibHeader(64)));
+        assert_eq!(err.to_string(), "unsupported DIB header of 64 bytes");
+        assert!(err.source().is_none());
+
+        let mut unsupported = bytes.clone();
+        unsupported[28] = 8;
+        unsupported[46] = 2;
+        assert!(matches!(
+            Bmp::read(&unsupported[..]),
+            Err(BmpError::UnsupportedBitsPerPixel(8))
+        ));
+
+        let mut unsupported = bytes.clone();
+        unsupported[30] = 2;
+        assert!(matches!(
+            Bmp::read(&unsupported[..]),
+            Err(BmpError::UnsupportedCompression(Compression::Rle4))
+        ));
+
+        let mut same_colors = bytes;
+        same_colors.copy_within(54..58, 58);
+        assert!(matches!(
+            Bmp::read(&same_colors[..]),
+            Err(BmpError::InvalidPalette)
+        ));
+    }
+
+    #[test]
+    fn test_read_with_limits() {
+        let bytes = Bmp::new(vec![vec![true; 1500]; 1000]).unwrap_err();
+        assert!(matches!(bytes, BmpError::Size(1500, 1000)));
+        let limits = Limits {
+            max_pixels: 2_000_000,
+            ..Default::default()
+        };
+        let bmp = Bmp::new_with_limits(vec![vec![true; 1500]; 1000], &limits).unwrap();
+        let bytes = bmp.to_bytes();
+        assert!(matches!(
+            Bmp::read(&bytes[..]),
+            Err(BmpError::Size(1500, 1000))
+        ));
+        assert_eq!(bmp, Bmp::read_with_limits(&bytes[..], &limits).unwrap());
+
+        let limits = Limits {
+            max_height: 17,
+            ..Default::default()
+        };
+        let file = File::open("test_bmp/monochrome_image.bmp").unwrap();
+        assert!(Bmp::read_with_limits(file, &limits).is_err());
     }
 }
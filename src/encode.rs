@@ -1,72 +1,196 @@
-use crate::bit::BitStreamWriter;
-use crate::{Bmp, BmpError, BmpHeader, B, HEADER_SIZE, M};
+use crate::bit;
+use crate::{Bmp, BmpError, BmpHeader, RowEncoder, RowOrder, WriteOptions, B, HEADER_SIZE, M};
+use std::convert::TryFrom;
 use std::io::Write;
 
 impl Bmp {
-    /// Write the monochrome bitmap to a Write type, such a File
-    pub fn write<T: Write>(&self, mut to: T) -> Result<(), BmpError> {
-        let height = self.height();
-        let width = self.width();
+    /// Write the monochrome bitmap to a Write type, such a File, using [`Bmp::write_options`],
+    /// thus keeping the palette and the resolution of the file this Bmp has been read from
+    /// the header and every row are written with a single call each
+    pub fn write<T: Write>(&self, to: T) -> Result<(), BmpError> {
+        self.write_with(to, &self.write_options())
+    }
 
-        let header = BmpHeader {
-            height,
-            width,
-            bg_is_zero: false,
-        };
-        let padding = header.padding() as u8;
+    /// return the options used by [`Bmp::write`], the default ones with the palette and the
+    /// resolution of this Bmp if any, may be used as a base to override some option
+    pub fn write_options(&self) -> WriteOptions {
+        let palette = self.palette().unwrap_or_default();
+        WriteOptions {
+            foreground: palette.foreground,
+            background: palette.background,
+            resolution: self.resolution().unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Write the monochrome bitmap to a Write type, such a File, customizing the output with
+    /// `options`, errors if foreground and background are the same color
+    pub fn write_with<T: Write>(&self, to: T, options: &WriteOptions) -> Result<(), BmpError> {
+        let mut encoder = RowEncoder::with_options(to, self.width, self.height, options)?;
+        let bytes_per_row = encoder.header.bytes_per_row() as usize;
+        let mut buffer = vec![0u8; bytes_per_row];
+        for n in 0..self.height as usize {
+            let index = encoder.header.row_index(n);
+            bit::words_to_bytes(self.row(index), &mut buffer);
+            encoder.write_row(&buffer)?;
+        }
+        encoder.finish()?;
+
+        Ok(())
+    }
 
+    /// return the bitmap encoded as it would be written by [`Bmp::write`]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write(&mut bytes)
+            .expect("writing to a Vec does not fail");
+        bytes
+    }
+
+    /// return the number of bytes written by [`Bmp::write`], useful to pre-size buffers
+    pub fn encoded_len(&self) -> usize {
+        self.header(&WriteOptions::default()).total_size() as usize
+    }
+
+    fn header(&self, options: &WriteOptions) -> BmpHeader {
+        BmpHeader::new(self.width, self.height, options)
+    }
+}
+
+impl<W: Write> RowEncoder<W> {
+    /// Write the header of a `width` x `height` monochrome bitmap to `to`, the rows are then
+    /// written with [`RowEncoder::write_row`], from the lower to the upper one
+    pub fn new(to: W, width: u32, height: u32) -> Result<Self, BmpError> {
+        RowEncoder::with_options(to, width, height, &WriteOptions::default())
+    }
+
+    /// Write the header like [`RowEncoder::new`] customizing the output with `options`, with
+    /// [`RowOrder::TopDown`] the rows are expected from the upper to the lower one
+    /// errors if width or height are zero or foreground and background are the same color
+    pub fn with_options(
+        mut to: W,
+        width: u32,
+        height: u32,
+        options: &WriteOptions,
+    ) -> Result<Self, BmpError> {
+        if width == 0 || height == 0 {
+            return Err(BmpError::Size(width, height));
+        }
+        if options.foreground == options.background {
+            return Err(BmpError::InvalidPalette);
+        }
+        let header = BmpHeader::new(width, height, options);
         header.write(&mut to)?;
+        let buffer = vec![0u8; header.bytes_per_row() as usize + header.padding() as usize];
+        Ok(RowEncoder {
+            to,
+            header,
+            buffer,
+            row: 0,
+        })
+    }
 
-        let mut writer = BitStreamWriter::new(&mut to);
+    /// return the width in pixel
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
 
-        for i in (0..self.height()).rev() {
-            for j in 0..self.width() {
-                if self.get(i, j) {
-                    writer.write(1, 1)?;
-                } else {
-                    writer.write(0, 1)?;
-                }
-            }
-            writer.write(0, (8 - (width % 8) as u8) % 8)?;
-            writer.write(0, padding * 8)?;
+    /// return the height in pixel, that is the number of rows expected
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    /// Write the next row, as packed bytes with the most significant bit of the first byte
+    /// being the left pixel and set bits being the dark ones, the bits past the width are ignored
+    /// errors if `row` isn't `(width + 7) / 8` bytes long or all the rows have been written
+    pub fn write_row(&mut self, row: &[u8]) -> Result<(), BmpError> {
+        let bytes_per_row = self.header.bytes_per_row() as usize;
+        if row.len() != bytes_per_row || self.row == self.header.height {
+            return Err(BmpError::InvalidRows);
         }
-        writer.flush()?;
+        self.buffer[..bytes_per_row].copy_from_slice(row);
+        self.buffer[bytes_per_row - 1] &= bit::last_byte_mask(self.header.width as usize);
+        self.to.write_all(&self.buffer)?;
+        self.row += 1;
+        Ok(())
+    }
 
+    /// Write the next row like [`RowEncoder::write_row`] with a `bool` for every pixel, `true`
+    /// being dark
+    /// errors if `row` isn't `width` long or all the rows have been written
+    pub fn write_row_pixels(&mut self, row: &[bool]) -> Result<(), BmpError> {
+        if row.len() != self.header.width as usize || self.row == self.header.height {
+            return Err(BmpError::InvalidRows);
+        }
+        for byte in self.buffer.iter_mut() {
+            *byte = 0;
+        }
+        for (j, pixel) in row.iter().enumerate() {
+            if *pixel {
+                self.buffer[j / 8] |= 0x80 >> (j % 8);
+            }
+        }
+        self.to.write_all(&self.buffer)?;
+        self.row += 1;
         Ok(())
     }
+
+    /// Flush and return the inner writer, errors if not all the rows have been written
+    pub fn finish(mut self) -> Result<W, BmpError> {
+        if self.row != self.header.height {
+            return Err(BmpError::InvalidRows);
+        }
+        self.to.flush()?;
+        Ok(self.to)
+    }
 }
 
 impl BmpHeader {
-    pub fn write<T: Write>(&self, to: &mut T) -> Result<(), BmpError> {
-        let bytes_per_row = self.bytes_per_row();
-        let padding = self.padding();
-        let data_size = (bytes_per_row + padding) * (self.height as u32);
-        let total_size = HEADER_SIZE + data_size;
-
-        to.write_all(&[B, M])?;
-        to.write_all(&total_size.to_le_bytes())?; // size of the bmp
-        to.write_all(&0u16.to_le_bytes())?; // creator1
-        to.write_all(&0u16.to_le_bytes())?; // creator2
-        to.write_all(&HEADER_SIZE.to_le_bytes())?; // pixel offset
-        to.write_all(&40u32.to_le_bytes())?; // dib header size
-        to.write_all(&(self.width as u32).to_le_bytes())?; // width
-        to.write_all(&(self.height as u32).to_le_bytes())?; // height
-        to.write_all(&1u16.to_le_bytes())?; // planes
-        to.write_all(&1u16.to_le_bytes())?; // bitsperpixel
-        to.write_all(&0u32.to_le_bytes())?; // no compression
-        to.write_all(&data_size.to_le_bytes())?; // size of the raw bitmap data with padding
-        to.write_all(&512u32.to_le_bytes())?; // hres
-        to.write_all(&512u32.to_le_bytes())?; // vres
-        to.write_all(&2u32.to_le_bytes())?; // num_colors
-        to.write_all(&2u32.to_le_bytes())?; // num_imp_colors
-
-        if self.bg_is_zero {
-            to.write_all(&0x00_00_00_00u32.to_le_bytes())?; // color_pallet 0
-            to.write_all(&0x00_FF_FF_FFu32.to_le_bytes())?; // color_pallet 1
-        } else {
-            to.write_all(&0x00_FF_FF_FFu32.to_le_bytes())?; // color_pallet 0
-            to.write_all(&0x00_00_00_00u32.to_le_bytes())?; // color_pallet 1
+    /// return the header of a `width` x `height` bitmap written with `options`
+    fn new(width: u32, height: u32, options: &WriteOptions) -> BmpHeader {
+        BmpHeader {
+            height,
+            width,
+            colors: [options.background, options.foreground],
+            bg_is_zero: false,
+            row_order: options.row_order,
+            resolution: Some(options.resolution),
+            ..Default::default()
         }
+    }
+
+    pub fn write<T: Write>(&self, to: &mut T) -> Result<(), BmpError> {
+        let data_size = u32::try_from(self.data_size())?;
+        let total_size = u32::try_from(self.total_size())?;
+        let width = i32::try_from(self.width)?;
+        let height = i32::try_from(self.height)?;
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE as usize);
+        bytes.extend_from_slice(&[B, M]);
+        bytes.extend_from_slice(&total_size.to_le_bytes()); // size of the bmp
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // creator1
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // creator2
+        bytes.extend_from_slice(&HEADER_SIZE.to_le_bytes()); // pixel offset
+        bytes.extend_from_slice(&40u32.to_le_bytes()); // dib header size
+        bytes.extend_from_slice(&width.to_le_bytes()); // width
+        let height = match self.row_order {
+            RowOrder::BottomUp => height,
+            RowOrder::TopDown => -height,
+        };
+        bytes.extend_from_slice(&height.to_le_bytes()); // height, negative if rows are top-down
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // planes
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // bitsperpixel
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // no compression
+        bytes.extend_from_slice(&data_size.to_le_bytes()); // size of the raw bitmap data with padding
+        let resolution = self.resolution.unwrap_or_default();
+        bytes.extend_from_slice(&resolution.x.to_le_bytes()); // hres
+        bytes.extend_from_slice(&resolution.y.to_le_bytes()); // vres
+        bytes.extend_from_slice(&2u32.to_le_bytes()); // num_colors
+        bytes.extend_from_slice(&2u32.to_le_bytes()); // num_imp_colors
+
+        bytes.extend_from_slice(&self.colors[0].to_u32().to_le_bytes()); // color_pallet 0
+        bytes.extend_from_slice(&self.colors[1].to_u32().to_le_bytes()); // color_pallet 1
+        to.write_all(&bytes)?;
 
         Ok(())
     }
@@ -74,7 +198,12 @@ impl BmpHeader {
 
 #[cfg(test)]
 mod test {
-    use crate::Bmp;
+    use crate::bit::BitStreamWriter;
+    use crate::{
+        Bmp, BmpError, InkMapping, Palette, ReadOptions, Resolution, Rgb, RowEncoder, RowOrder,
+        WriteOptions,
+    };
+    use std::fs::File;
     use std::io::Cursor;
 
     #[test]
@@ -95,4 +224,217 @@ mod test {
         let bmp = Bmp::read(buffer).unwrap();
         assert_eq!(bmp_created, bmp);
     }
+
+    #[test]
+    fn test_write_matches_bit_writer() {
+        let bmp = Bmp::read(File::open("test_bmp/qr_not_normalized.bmp").unwrap()).unwrap();
+        let mut expected = vec![];
+        let header = bmp.header(&bmp.write_options());
+        header.write(&mut expected).unwrap();
+        let width = bmp.width() as u8;
+        let padding = header.padding() as u8;
+        let mut writer = BitStreamWriter::new(&mut expected);
+        for i in (0..bmp.height()).rev() {
+            for j in 0..bmp.width() {
+                writer.write(bmp.get(i, j) as u64, 1).unwrap();
+            }
+            writer.write(0, (8 - width % 8) % 8).unwrap();
+            writer.write(0, padding * 8).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(expected.len(), bmp.encoded_len());
+        assert_eq!(expected, bmp.to_bytes());
+    }
+
+    #[test]
+    fn test_write_top_down() {
+        let bmp = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        let options = WriteOptions {
+            row_order: RowOrder::TopDown,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        bmp.write_with(&mut bytes, &options).unwrap();
+        assert_eq!(bytes.len(), bmp.encoded_len());
+        assert_eq!(&bytes[22..26], &(-18i32).to_le_bytes());
+        assert_eq!(bmp, Bmp::read(&bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn test_write_colors() {
+        let bmp = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        let navy = Rgb { r: 0, g: 0, b: 128 };
+        let gold = Rgb {
+            r: 255,
+            g: 215,
+            b: 0,
+        };
+        let options = WriteOptions {
+            foreground: navy,
+            background: gold,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        bmp.write_with(&mut bytes, &options).unwrap();
+        let read = Bmp::read(&bytes[..]).unwrap();
+        assert_eq!(bmp, read);
+        let palette = Palette {
+            background: gold,
+            foreground: navy,
+        };
+        assert_eq!(Some(palette), read.palette());
+
+        // light on dark is read back inverted unless the ink mapping is explicit
+        let options = WriteOptions {
+            foreground: gold,
+            background: navy,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        bmp.write_with(&mut bytes, &options).unwrap();
+        assert_eq!(bmp.inverse(), Bmp::read(&bytes[..]).unwrap());
+        let read_options = ReadOptions {
+            ink: InkMapping::One,
+            ..Default::default()
+        };
+        assert_eq!(bmp, Bmp::read_with(&bytes[..], &read_options).unwrap());
+
+        let options = WriteOptions {
+            foreground: gold,
+            background: gold,
+            ..Default::default()
+        };
+        assert!(bmp.write_with(vec![], &options).is_err());
+    }
+
+    #[test]
+    fn test_row_encoder() {
+        let bmp = Bmp::read(File::open("test_bmp/qr_not_normalized.bmp").unwrap()).unwrap();
+        let mut encoder = RowEncoder::new(vec![], bmp.width(), bmp.height()).unwrap();
+        for i in (0..bmp.height()).rev() {
+            let row: Vec<bool> = (0..bmp.width()).map(|j| bmp.get(i, j)).collect();
+            encoder.write_row_pixels(&row).unwrap();
+        }
+        assert!(encoder.write_row_pixels(&[true]).is_err());
+        assert_eq!(bmp.to_bytes(), encoder.finish().unwrap());
+
+        let options = WriteOptions {
+            row_order: RowOrder::TopDown,
+            ..Default::default()
+        };
+        let mut encoder = RowEncoder::with_options(vec![], 10, 2, &options).unwrap();
+        assert_eq!((10, 2), (encoder.width(), encoder.height()));
+        assert!(encoder.write_row(&[0xFF]).is_err());
+        // bits past the width are ignored
+        encoder.write_row(&[0xFF, 0xFF]).unwrap();
+        assert!(encoder.write_row(&[0xFF, 0xFF, 0xFF]).is_err());
+        let unfinished = RowEncoder::with_options(vec![], 10, 2, &options).unwrap();
+        assert!(matches!(unfinished.finish(), Err(BmpError::InvalidRows)));
+        encoder.write_row(&[0x80, 0x40]).unwrap();
+        let bytes = encoder.finish().unwrap();
+        assert_eq!(&bytes[22..26], &(-2i32).to_le_bytes());
+        assert_eq!(&bytes[62..66], &[0xFF, 0xC0, 0, 0]);
+        let expected = Bmp::new(vec![
+            vec![true; 10],
+            vec![
+                true, false, false, false, false, false, false, false, false, true,
+            ],
+        ])
+        .unwrap();
+        assert_eq!(expected, Bmp::read(&bytes[..]).unwrap());
+
+        assert!(matches!(
+            RowEncoder::new(vec![], 0, 2),
+            Err(BmpError::Size(0, 2))
+        ));
+    }
+
+    #[test]
+    fn test_write_wide() {
+        // a receipt-like strip wider than u16::MAX
+        let mut row = vec![false; 70_000];
+        row[0] = true;
+        row[69_999] = true;
+        let bmp = Bmp::new(vec![row.clone(), row]).unwrap();
+        assert_eq!(bmp.width(), 70_000);
+        let bytes = bmp.to_bytes();
+        assert_eq!(&bytes[18..22], &70_000u32.to_le_bytes());
+        let read = Bmp::read(&bytes[..]).unwrap();
+        assert!(read.get(1, 69_999));
+        assert!(!read.get(1, 69_998));
+        assert_eq!(bmp, read);
+    }
+
+    #[test]
+    fn test_write_palette() {
+        let bmp = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
+        assert_eq!(Some(Palette::default()), bmp.palette());
+        let navy = Rgb { r: 0, g: 0, b: 128 };
+        let gold = Rgb {
+            r: 255,
+            g: 215,
+            b: 0,
+        };
+        let palette = Palette {
+            background: gold,
+            foreground: navy,
+        };
+        let options = WriteOptions {
+            foreground: navy,
+            background: gold,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        bmp.write_with(&mut bytes, &options).unwrap();
+
+        let read = Bmp::read(&bytes[..]).unwrap();
+        let transformed = read.mul(2).unwrap().add_white_border(1).unwrap();
+        let read = Bmp::read(&transformed.to_bytes()[..]).unwrap();
+        assert_eq!(Some(palette), read.palette());
+        assert_eq!(transformed, read);
+
+        // a palette read with an explicit ink mapping is kept as well
+        let read_options = ReadOptions {
+            ink: InkMapping::One,
+            ..Default::default()
+        };
+        let inverted = Bmp::read_with(&bytes[..], &read_options).unwrap();
+        let read = Bmp::read_with(&inverted.to_bytes()[..], &read_options).unwrap();
+        assert_eq!(inverted.palette(), read.palette());
+        assert_eq!(inverted, read);
+
+        let mut bmp = read;
+        bmp.set_palette(Palette::default());
+        let read = Bmp::read(&bmp.to_bytes()[..]).unwrap();
+        assert_eq!(Some(Palette::default()), read.palette());
+        assert_eq!(bmp, read);
+
+        let created = Bmp::new(vec![vec![true, false]]).unwrap();
+        assert_eq!(None, created.palette());
+        assert_eq!(Rgb::BLACK, created.write_options().foreground);
+        assert_eq!(Rgb::WHITE, created.write_options().background);
+    }
+
+    #[test]
+    fn test_write_resolution() {
+        let mut bmp = Bmp::read(File::open("test_bmp/test1.bmp").unwrap()).unwrap();
+        assert_eq!(Some(Resolution { x: 512, y: 512 }), bmp.resolution());
+        bmp.set_resolution(Resolution::from_dpi(300));
+
+        let read = Bmp::read(&bmp.to_bytes()[..]).unwrap();
+        assert_eq!(Some(Resolution::from_dpi(300)), read.resolution());
+        let transformed = read.mul(3).unwrap().add_white_border(2).unwrap();
+        let read = Bmp::read(&transformed.to_bytes()[..]).unwrap();
+        assert_eq!((300, 300), read.resolution().unwrap().dpi());
+
+        let options = WriteOptions {
+            resolution: Resolution { x: 100, y: 200 },
+            ..bmp.write_options()
+        };
+        let mut bytes = vec![];
+        bmp.write_with(&mut bytes, &options).unwrap();
+        let read = Bmp::read(&bytes[..]).unwrap();
+        assert_eq!(Some(Resolution { x: 100, y: 200 }), read.resolution());
+    }
 }
@@ -1,15 +1,17 @@
 //! fuzzing!
 
-use crate::{check_size, Bmp, BmpError};
+use crate::{Bmp, BmpError, Limits};
 use arbitrary::Arbitrary;
 use image::{DynamicImage, GenericImageView, ImageFormat, Rgba};
 use std::io::Cursor;
 
 impl arbitrary::Arbitrary for Bmp {
     fn arbitrary(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<Self> {
-        let width = u16::arbitrary(u)?;
-        let height = u16::arbitrary(u)?;
-        check_size(width, height).map_err(|_| arbitrary::Error::IncorrectFormat)?;
+        let width = u16::arbitrary(u)? as u32;
+        let height = u16::arbitrary(u)? as u32;
+        Limits::default()
+            .check(width, height)
+            .map_err(|_| arbitrary::Error::IncorrectFormat)?;
         let mut rows = Vec::with_capacity(height as usize);
         for _ in 0..height {
             let mut row = Vec::with_capacity(width as usize);
@@ -36,6 +38,20 @@ pub enum Op {
     RemoveBorder,
     /// bmp.normalize
     Normalize,
+    /// bmp.rotate90
+    Rotate90,
+    /// bmp.rotate180
+    Rotate180,
+    /// bmp.rotate270
+    Rotate270,
+    /// bmp.flip_horizontal
+    FlipHorizontal,
+    /// bmp.flip_vertical
+    FlipVertical,
+    /// bmp.transpose
+    Transpose,
+    /// bmp.crop
+    Crop(u16, u16, u16, u16),
 }
 
 /// Used for fuzz testing creating a random Bmp and a random Op to apply to
@@ -57,8 +73,8 @@ impl Bmp {
             image::load_from_memory_with_format(&cursor.into_inner(), ImageFormat::Bmp).unwrap();
 
         let (width, height) = image.dimensions();
-        assert_eq!(width, self.width() as u32);
-        assert_eq!(height, self.height() as u32);
+        assert_eq!(width, self.width());
+        assert_eq!(height, self.height());
         assert_eq!(
             to_test_string(&image),
             self.display().to_string().trim_end()
@@ -99,6 +115,15 @@ impl BmpAndOps {
                 Op::Border(border) => bmp.add_white_border(*border)?,
                 Op::Normalize => bmp.normalize(),
                 Op::RemoveBorder => bmp.remove_white_border(),
+                Op::Rotate90 => bmp.rotate90(),
+                Op::Rotate180 => bmp.rotate180(),
+                Op::Rotate270 => bmp.rotate270(),
+                Op::FlipHorizontal => bmp.flip_horizontal(),
+                Op::FlipVertical => bmp.flip_vertical(),
+                Op::Transpose => bmp.transpose(),
+                Op::Crop(row, column, width, height) => {
+                    bmp.crop(*row as u32, *column as u32, *width as u32, *height as u32)?
+                }
             };
         }
         Ok(())
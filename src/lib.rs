@@ -14,6 +14,7 @@ use std::num::TryFromIntError;
 mod bit;
 mod decode;
 mod encode;
+mod threshold;
 
 #[cfg(feature = "fuzz")]
 pub mod fuzz;
@@ -21,38 +22,185 @@ pub mod fuzz;
 const B: u8 = 66;
 const M: u8 = 77;
 const COLOR_PALLET_SIZE: u32 = 2 * 4; // 2 colors each 4 bytes
-const HEADER_SIZE: u32 = 2 + 12 + 40 + COLOR_PALLET_SIZE;
-
-/// The `Bmp` struct contains the data as a vector of vectors of booleans.
-/// Each boolean represent a pixel.
-/// In `rows` the first element is the upper row, inside the first vector there are the pixel
-/// from left to right, thus `rows[0][0]` is the upper-left element.
-/// Max len of the vetors (both rows and colums) is [u16::MAX]`
+const FILE_HEADER_SIZE: u32 = 2 + 12;
+const HEADER_SIZE: u32 = FILE_HEADER_SIZE + 40 + COLOR_PALLET_SIZE;
+
+/// The `Bmp` struct contains the pixels packed as bits in a single vector of words.
+/// Each bit represent a pixel, set bits are the dark ones.
+/// Rows are stored from the upper to the lower one, every row starts on a new word and contains
+/// the pixel from left to right, thus the most significant bit of the first word is the
+/// upper-left element.
+/// Max len of both rows and colums is [u32::MAX], bounded in practice by [`Limits`]
 /// Note in the serialized format the first element is the lower-left pixel
 /// see [BMP file format](https://en.wikipedia.org/wiki/BMP_file_format)
-#[derive(PartialEq, Eq, Clone)]
+/// Two `Bmp` are equal if they have the same pixels, metadata read from the header are ignored
+#[derive(Clone)]
 pub struct Bmp {
-    rows: Vec<Vec<bool>>,
+    width: u32,
+    height: u32,
+    data: Vec<u64>,
+    meta: Metadata,
+}
+
+/// Rectangle of pixels with the upper-left corner at `row` and `column`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// Upper row
+    pub row: u32,
+    /// Left column
+    pub column: u32,
+    /// Width in pixel
+    pub width: u32,
+    /// Height in pixel
+    pub height: u32,
+}
+
+/// Rectangular region of a [`Bmp`] borrowing its pixels, see [`Bmp::view`]
+#[derive(Debug, Clone, Copy)]
+pub struct BmpView<'a> {
+    bmp: &'a Bmp,
+    region: Rect,
+}
+
+/// Information read from the header which doesn't affect the pixels, kept by transformations
+#[derive(Debug, Clone, Default)]
+struct Metadata {
+    color_space: Option<ColorSpace>,
+    palette: Option<Palette>,
+    resolution: Option<Resolution>,
+}
+
+/// Colour space information of BITMAPV4HEADER and BITMAPV5HEADER
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorSpace {
+    /// Colour space type, such as `0x7352_4742` ("sRGB") or `0x4D42_4544` (embedded profile)
+    pub cs_type: u32,
+    /// CIEXYZ coordinates of the red, green and blue endpoints, in 2.30 fixed point
+    pub endpoints: [[u32; 3]; 3],
+    /// Gamma of the red, green and blue channels, in 16.16 fixed point
+    pub gamma: [u32; 3],
+    /// Rendering intent, present only in BITMAPV5HEADER
+    pub intent: Option<u32>,
+    /// Embedded ICC profile, present only in BITMAPV5HEADER when stored before the pixel data
+    pub profile: Option<Vec<u8>>,
+}
+
+impl PartialEq for Bmp {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width && self.height == other.height && self.data == other.data
+    }
 }
 
-/// Internal error struct
+impl Eq for Bmp {}
+
+/// Errors returned by this library
 #[derive(Debug)]
 pub enum BmpError {
-    /// Generic
-    Generic,
-    /// Relative to the content
-    Content,
-    /// Relative to the header
-    Header,
-    /// Relative to the data
-    Data,
-    /// Relative to the size
-    Size(u16, u16),
+    /// Error of the underlying reader or writer
+    Io(std::io::Error),
+    /// The file doesn't start with the `BM` magic bytes
+    BadMagic([u8; 2]),
+    /// The DIB header size doesn't match any supported variant
+    UnsupportedDibHeader(u32),
+    /// The bits per pixel are not supported, only monochrome bitmaps are
+    UnsupportedBitsPerPixel(u16),
+    /// The compression of the pixel data is not supported
+    UnsupportedCompression(Compression),
+    /// The number of color planes is not 1
+    InvalidPlanes(u16),
+    /// The width declared in the header is negative
+    NegativeWidth(i32),
+    /// The palette has less than 2 or more than 256 colors, or the 2 colors are the same
+    InvalidPalette,
+    /// The pixel data offset points inside the header or too far from it
+    InvalidPixelOffset(u32),
+    /// The file size declared in the header is smaller than the pixel data offset and size
+    InvalidFileSize(u32),
+    /// The pixel data size declared in the header is smaller than the width and height require
+    InvalidImageSize(u32),
+    /// The pixel data ended before row `row`, counting rows in file order
+    TruncatedData {
+        /// The row which couldn't be read
+        row: u32,
+        /// The error returned by the reader
+        source: std::io::Error,
+    },
+    /// The rows are empty or have different length
+    InvalidRows,
+    /// The scale factor is less than 2 or doesn't divide the image dimensions
+    BadScaleFactor(u8),
+    /// The block of pixels starting at (`row`, `column`) is not of the same color
+    NonUniformBlock {
+        /// Upper row of the block
+        row: u32,
+        /// Left column of the block
+        column: u32,
+    },
+    /// A dimension overflows the maximum value supported
+    Overflow,
+    /// The width and height are zero or exceed the limits, see [`Limits`]
+    Size(u32, u32),
+    /// The region is empty or exceeds the image
+    InvalidRegion(Rect),
 }
 
 impl Display for BmpError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            BmpError::Io(e) => write!(f, "i/o error: {}", e),
+            BmpError::BadMagic(magic) => write!(
+                f,
+                "not a bmp file, it starts with {:02x}{:02x} instead of \"BM\"",
+                magic[0], magic[1]
+            ),
+            BmpError::UnsupportedDibHeader(size) => {
+                write!(f, "unsupported DIB header of {} bytes", size)
+            }
+            BmpError::UnsupportedBitsPerPixel(bpp) => {
+                write!(f, "unsupported {} bits per pixel", bpp)
+            }
+            BmpError::UnsupportedCompression(compression) => {
+                write!(f, "unsupported compression {:?}", compression)
+            }
+            BmpError::InvalidPlanes(planes) => {
+                write!(f, "invalid number of color planes {}, must be 1", planes)
+            }
+            BmpError::NegativeWidth(width) => write!(f, "invalid negative width {}", width),
+            BmpError::InvalidPalette => write!(f, "invalid palette"),
+            BmpError::InvalidPixelOffset(offset) => {
+                write!(f, "invalid pixel data offset {}", offset)
+            }
+            BmpError::InvalidFileSize(size) => write!(f, "invalid file size {}", size),
+            BmpError::InvalidImageSize(size) => write!(f, "invalid pixel data size {}", size),
+            BmpError::TruncatedData { row, source } => {
+                write!(f, "pixel data truncated at row {}: {}", row, source)
+            }
+            BmpError::InvalidRows => write!(f, "rows are empty or of different length"),
+            BmpError::BadScaleFactor(factor) => write!(f, "invalid scale factor {}", factor),
+            BmpError::NonUniformBlock { row, column } => write!(
+                f,
+                "block at row {} column {} has pixels of different colors",
+                row, column
+            ),
+            BmpError::Overflow => write!(f, "dimension overflow"),
+            BmpError::Size(width, height) => {
+                write!(f, "invalid size {}x{}, see limits", width, height)
+            }
+            BmpError::InvalidRegion(rect) => write!(
+                f,
+                "invalid region {}x{} at ({}, {})",
+                rect.width, rect.height, rect.row, rect.column
+            ),
+        }
+    }
+}
+
+impl std::iter::FromIterator<Vec<bool>> for Bmp {
+    /// Creates a Bmp from the rows like [`Bmp::new`]
+    /// panics if the rows are empty, have different length or exceed the default [`Limits`], use
+    /// [`Bmp::new`] to handle the error
+    fn from_iter<I: IntoIterator<Item = Vec<bool>>>(iter: I) -> Self {
+        Bmp::new(iter.into_iter().collect()).expect("invalid rows")
     }
 }
 
@@ -62,106 +210,846 @@ impl Debug for Bmp {
     }
 }
 
-impl std::error::Error for BmpError {}
+impl std::error::Error for BmpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BmpError::Io(e) | BmpError::TruncatedData { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<std::num::TryFromIntError> for BmpError {
     fn from(_: TryFromIntError) -> Self {
-        BmpError::Generic
+        BmpError::Overflow
+    }
+}
+
+/// Order in which rows are stored in the serialized format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowOrder {
+    /// The first row in the file is the lower one, the most common layout
+    BottomUp,
+    /// The first row in the file is the upper one, serialized with a negative height
+    TopDown,
+}
+
+impl Default for RowOrder {
+    fn default() -> Self {
+        RowOrder::BottomUp
+    }
+}
+
+impl RowOrder {
+    /// return the index of the row stored at position `n` of `height` rows, where 0 is the
+    /// upper row
+    fn row_index(self, n: usize, height: u32) -> usize {
+        match self {
+            RowOrder::BottomUp => height as usize - 1 - n,
+            RowOrder::TopDown => n,
+        }
     }
 }
 
+/// A color of the palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    /// Red channel
+    pub r: u8,
+    /// Green channel
+    pub g: u8,
+    /// Blue channel
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Black, the default foreground
+    pub const BLACK: Rgb = Rgb { r: 0, g: 0, b: 0 };
+    /// White, the default background
+    pub const WHITE: Rgb = Rgb {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+
+    /// return the luminance of the color, from 0 (black) to 255_000 (white), using Rec. 601 weights
+    pub fn luminance(&self) -> u32 {
+        299 * self.r as u32 + 587 * self.g as u32 + 114 * self.b as u32
+    }
+
+    /// create the color from a palette entry as stored in the file, blue in the lowest byte
+    fn from_u32(value: u32) -> Rgb {
+        Rgb {
+            r: (value >> 16) as u8,
+            g: (value >> 8) as u8,
+            b: value as u8,
+        }
+    }
+
+    /// return the palette entry as stored in the file, blue in the lowest byte
+    fn to_u32(self) -> u32 {
+        (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+}
+
+/// The two colors of a monochrome bitmap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// Color of the pixels which are `false` in the [`Bmp`]
+    pub background: Rgb,
+    /// Color of the pixels which are `true` in the [`Bmp`]
+    pub foreground: Rgb,
+}
+
+impl Default for Palette {
+    /// black on white
+    fn default() -> Self {
+        Palette {
+            background: Rgb::WHITE,
+            foreground: Rgb::BLACK,
+        }
+    }
+}
+
+/// Which one of the two palette colors is considered ink, the pixels `true` in the [`Bmp`]
+///
+/// The palette of BI_RLE8 and BI_RLE4 compressed bitmaps may have more colors:
+/// [`InkMapping::Luminance`] makes ink the colors darker than the luminance midpoint, while
+/// [`InkMapping::Zero`] and [`InkMapping::One`] make ink only the pixels with that index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InkMapping {
+    /// The darker color is ink, if the luminance is the same the color at index 1
+    Luminance,
+    /// The color at index 0 is ink
+    Zero,
+    /// The color at index 1 is ink
+    One,
+}
+
+impl Default for InkMapping {
+    fn default() -> Self {
+        InkMapping::Luminance
+    }
+}
+
+impl InkMapping {
+    /// return whether the bit 0 is ink given the palette `colors`
+    fn zero_is_ink(self, colors: &[Rgb; 2]) -> bool {
+        match self {
+            InkMapping::Luminance => colors[0].luminance() < colors[1].luminance(),
+            InkMapping::Zero => true,
+            InkMapping::One => false,
+        }
+    }
+}
+
+/// Variant of the DIB header, identified by its size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DibHeader {
+    /// OS/2 BITMAPCOREHEADER, 12 bytes
+    Core,
+    /// BITMAPINFOHEADER, 40 bytes
+    Info,
+    /// BITMAPV2INFOHEADER, 52 bytes
+    V2,
+    /// BITMAPV3INFOHEADER, 56 bytes
+    V3,
+    /// BITMAPV4HEADER, 108 bytes
+    V4,
+    /// BITMAPV5HEADER, 124 bytes
+    V5,
+}
+
+impl DibHeader {
+    /// return the size in bytes of the DIB header
+    pub fn size(self) -> u32 {
+        match self {
+            DibHeader::Core => 12,
+            DibHeader::Info => 40,
+            DibHeader::V2 => 52,
+            DibHeader::V3 => 56,
+            DibHeader::V4 => 108,
+            DibHeader::V5 => 124,
+        }
+    }
+
+    fn from_size(size: u32) -> Option<DibHeader> {
+        match size {
+            12 => Some(DibHeader::Core),
+            40 => Some(DibHeader::Info),
+            52 => Some(DibHeader::V2),
+            56 => Some(DibHeader::V3),
+            108 => Some(DibHeader::V4),
+            124 => Some(DibHeader::V5),
+            _ => None,
+        }
+    }
+}
+
+/// Compression of the pixel data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// BI_RGB, no compression
+    Uncompressed,
+    /// BI_RLE8, run length encoding of 8 bits per pixel bitmaps
+    Rle8,
+    /// BI_RLE4, run length encoding of 4 bits per pixel bitmaps
+    Rle4,
+    /// BI_BITFIELDS, uncompressed with color masks
+    Bitfields,
+    /// Any other compression method
+    Other(u32),
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Uncompressed
+    }
+}
+
+impl Compression {
+    fn from_u32(value: u32) -> Compression {
+        match value {
+            0 => Compression::Uncompressed,
+            1 => Compression::Rle8,
+            2 => Compression::Rle4,
+            3 => Compression::Bitfields,
+            other => Compression::Other(other),
+        }
+    }
+}
+
+/// Masks selecting the bits of every channel in a pixel of 16 or 32 bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChannelMasks {
+    /// Red channel
+    pub red: u32,
+    /// Green channel
+    pub green: u32,
+    /// Blue channel
+    pub blue: u32,
+    /// Alpha channel, zero if missing
+    pub alpha: u32,
+}
+
+/// Information contained in the header of a BMP file, see [`BmpInfo::read`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BmpInfo {
+    /// Size of the file declared in the header
+    pub file_size: u32,
+    /// Offset of the pixel data from the start of the file
+    pub pixel_offset: u32,
+    /// Variant of the DIB header
+    pub dib_header: DibHeader,
+    /// Width in pixel
+    pub width: u32,
+    /// Height in pixel
+    pub height: u32,
+    /// Order of the rows in the pixel data
+    pub row_order: RowOrder,
+    /// Number of color planes
+    pub planes: u16,
+    /// Number of bits of every pixel
+    pub bits_per_pixel: u16,
+    /// Compression of the pixel data
+    pub compression: Compression,
+    /// Size of the pixel data declared in the header, may be 0 for uncompressed bitmaps
+    pub image_size: u32,
+    /// Resolution, missing in BITMAPCOREHEADER
+    pub resolution: Option<Resolution>,
+    /// Colors of the palette, in index order
+    pub palette: Vec<Rgb>,
+    /// Masks of the color channels, present with [`Compression::Bitfields`]
+    pub masks: Option<ChannelMasks>,
+    /// Colour space, present in BITMAPV4HEADER and BITMAPV5HEADER, note the embedded profile
+    /// is not read
+    pub color_space: Option<ColorSpace>,
+    profile_position: Option<(u32, u32)>,
+}
+
+/// Decoder returning the rows of a monochrome bitmap one at a time, in the order they are stored
+/// in the file, see [`RowDecoder::new`]
+/// Only the current row is kept in memory, thus only `max_width` and `max_alloc` of the
+/// [`Limits`] apply, to the row, and images of any height are decoded
 #[derive(Debug)]
+pub struct RowDecoder<R> {
+    from: R,
+    header: BmpHeader,
+    buffer: Vec<u8>,
+    pixels: Vec<bool>,
+    row: u32,
+    rle: RleState,
+    mode: DecodeMode,
+    warnings: Vec<DecodeWarning>,
+}
+
+/// Position in the pixel data of a run length encoded bitmap
+#[derive(Debug, Default)]
+struct RleState {
+    /// Column of the next pixel
+    x: usize,
+    /// Rows skipped by a delta escape still to be returned
+    skipped_rows: u32,
+    /// The end of bitmap escape has been read
+    end: bool,
+}
+
+/// Encoder writing a monochrome bitmap one row at a time, in the order they are stored in the
+/// file, see [`RowEncoder::new`]
+/// Only the current row is kept in memory, thus the image may be generated incrementally
+#[derive(Debug)]
+pub struct RowEncoder<W> {
+    to: W,
+    header: BmpHeader,
+    buffer: Vec<u8>,
+    row: u32,
+}
+
+/// Monochrome bitmap borrowing the pixels of an encoded file, see [`BmpRef::new`]
+/// Pixels are accessed directly in the encoded bytes without allocating, use [`BmpRef::to_bmp`]
+/// to obtain an owned [`Bmp`]
+#[derive(Debug, Clone)]
+pub struct BmpRef<'a> {
+    header: BmpHeader,
+    pixels: &'a [u8],
+    profile: Option<&'a [u8]>,
+}
+
+/// How the luminance of the pixels is binarised by [`Bmp::read_thresholded`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threshold {
+    /// Pixels with luminance, from 0 to 255, lower than the value are dark
+    Fixed(u8),
+    /// The value is chosen with Otsu's method, maximizing the variance between the dark and
+    /// the light pixels
+    Otsu,
+}
+
+/// Options used by [`Bmp::read_with`]
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// How palette colors are mapped to pixel values
+    pub ink: InkMapping,
+    /// Limits of the decoded bitmap
+    pub limits: Limits,
+    /// How inconsistent or truncated files are handled
+    pub mode: DecodeMode,
+}
+
+/// How inconsistent or truncated files are handled when decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// Errors if the pixel data is truncated or the file and pixel data sizes declared in the
+    /// header are wrong
+    Strict,
+    /// Ignores wrong sizes and fills the missing rows of truncated files with background,
+    /// reporting every problem as a [`DecodeWarning`]
+    Lenient,
+}
+
+impl Default for DecodeMode {
+    fn default() -> Self {
+        DecodeMode::Strict
+    }
+}
+
+/// Problem found decoding in [`DecodeMode::Lenient`], which would be an error in
+/// [`DecodeMode::Strict`], see [`Bmp::read_with_warnings`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeWarning {
+    /// The pixel data ended at `row`, counting rows in file order, the missing pixels are
+    /// background
+    TruncatedData {
+        /// The row which couldn't be read entirely
+        row: u32,
+    },
+    /// The file size declared in the header is smaller than the pixel data offset and size
+    InvalidFileSize(u32),
+    /// The pixel data size declared in the header is smaller than the width and height require
+    InvalidImageSize(u32),
+}
+
+/// Limits enforced when creating, decoding and enlarging a Bmp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Max width in pixel, [`i32::MAX`] is enforced anyway as the header can't store more
+    pub max_width: u32,
+    /// Max height in pixel, [`i32::MAX`] is enforced anyway as the header can't store more
+    pub max_height: u32,
+    /// Max number of pixels, width * height
+    pub max_pixels: u64,
+    /// Max bytes allocated to store the pixels
+    pub max_alloc: usize,
+}
+
+impl Default for Limits {
+    /// arbitrary limit width * height <= 1 million, without further restrictions
+    fn default() -> Self {
+        Limits {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: 1_000_000,
+            max_alloc: usize::MAX,
+        }
+    }
+}
+
+impl Limits {
+    /// check a bitmap of `width` x `height` pixels is within limits and may be written, as the
+    /// header stores signed 32 bits width and height and a 32 bits file size, height and width
+    /// must be > 0
+    pub fn check(&self, width: u32, height: u32) -> Result<(), BmpError> {
+        let pixels = width as u64 * height as u64;
+        // computed in u64 as the size may not fit in usize on 32 bits targets
+        let words = (width as u64 + bit::WORD_BITS as u64 - 1) / bit::WORD_BITS as u64;
+        let alloc = words * height as u64 * std::mem::size_of::<u64>() as u64;
+        let file_size = HEADER_SIZE as u64 + (width as u64 + 31) / 32 * 4 * height as u64;
+        if width > 0
+            && height > 0
+            && width <= self.max_width.min(i32::MAX as u32)
+            && height <= self.max_height.min(i32::MAX as u32)
+            && file_size <= u32::MAX as u64
+            && pixels <= self.max_pixels
+            && alloc <= self.max_alloc as u64
+        {
+            Ok(())
+        } else {
+            Err(BmpError::Size(width, height))
+        }
+    }
+
+    /// check a bitmap of `width` x `height` pixels decoded keeping in memory only a row of
+    /// `row_bytes` bytes is within limits, thus `max_height` and `max_pixels` don't apply,
+    /// height and width must be > 0
+    fn check_row(&self, width: u32, height: u32, row_bytes: u64) -> Result<(), BmpError> {
+        if width > 0 && height > 0 && width <= self.max_width && row_bytes <= self.max_alloc as u64
+        {
+            Ok(())
+        } else {
+            Err(BmpError::Size(width, height))
+        }
+    }
+}
+
+/// Physical resolution of the bitmap, in pixels per metre as stored in the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    /// Horizontal pixels per metre
+    pub x: u32,
+    /// Vertical pixels per metre
+    pub y: u32,
+}
+
+impl Default for Resolution {
+    /// 512 pixels per metre, the value historically written by this library
+    fn default() -> Self {
+        Resolution { x: 512, y: 512 }
+    }
+}
+
+impl Resolution {
+    /// Creates a resolution of `dpi` dots per inch in both directions
+    pub fn from_dpi(dpi: u32) -> Resolution {
+        let ppm = dpi_to_ppm(dpi);
+        Resolution { x: ppm, y: ppm }
+    }
+
+    /// return the horizontal and vertical resolution in dots per inch, rounded
+    pub fn dpi(&self) -> (u32, u32) {
+        (ppm_to_dpi(self.x), ppm_to_dpi(self.y))
+    }
+}
+
+/// 1 inch is 0.0254 metres
+fn dpi_to_ppm(dpi: u32) -> u32 {
+    ((dpi as u64 * 10_000 + 127) / 254) as u32
+}
+
+fn ppm_to_dpi(ppm: u32) -> u32 {
+    ((ppm as u64 * 254 + 5_000) / 10_000) as u32
+}
+
+/// Options used by [`Bmp::write_with`]
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Color of the pixels which are `true`, written as palette index 1
+    /// Note if it's lighter than `background` reading the file back with the default
+    /// [`InkMapping::Luminance`] returns the inverted bitmap
+    pub foreground: Rgb,
+    /// Color of the pixels which are `false`, written as palette index 0
+    pub background: Rgb,
+    /// Order of the rows in the written file
+    pub row_order: RowOrder,
+    /// Resolution written in the header
+    pub resolution: Resolution,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            foreground: Rgb::BLACK,
+            background: Rgb::WHITE,
+            row_order: RowOrder::default(),
+            resolution: Resolution::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 struct BmpHeader {
-    height: u16,
-    width: u16,
+    height: u32,
+    width: u32,
+    colors: [Rgb; 2],
     bg_is_zero: bool,
+    row_order: RowOrder,
+    resolution: Option<Resolution>,
+    color_space: Option<ColorSpace>,
+    compression: Compression,
+    /// for run length encoded bitmaps, whether every palette index is the darker color
+    dark_indexes: Vec<bool>,
+    file_size: u32,
+    image_size: u32,
+    pixel_offset: u32,
 }
 
 impl Bmp {
     /// Creates a new Bmp, failing if `rows` is empty or it's first element is empty
-    /// or it's elements has different len
+    /// or it's elements has different len or the default [`Limits`] are exceeded
     pub fn new(rows: Vec<Vec<bool>>) -> Result<Bmp, BmpError> {
+        Bmp::new_with_limits(rows, &Limits::default())
+    }
+
+    /// Creates a new Bmp like [`Bmp::new`] enforcing the given `limits`
+    pub fn new_with_limits(rows: Vec<Vec<bool>>, limits: &Limits) -> Result<Bmp, BmpError> {
         if rows.is_empty() || rows[0].is_empty() || !rows.iter().all(|e| e.len() == rows[0].len()) {
-            Err(BmpError::Data)
+            Err(BmpError::InvalidRows)
         } else {
-            check_size(u16::try_from(rows.len())?, u16::try_from(rows[0].len())?)?;
-            Ok(Bmp { rows })
+            let height = u32::try_from(rows.len())?;
+            let width = u32::try_from(rows[0].len())?;
+            limits.check(width, height)?;
+            let mut bmp = Bmp::zeroed(width, height);
+            for (i, row) in rows.iter().enumerate() {
+                let words = bmp.row_mut(i);
+                for (j, pixel) in row.iter().enumerate() {
+                    if *pixel {
+                        bit::set_bit(words, j, true);
+                    }
+                }
+            }
+            Ok(bmp)
+        }
+    }
+
+    /// Creates a Bmp of `width` x `height` pixels all set to `value`, erroring if the default
+    /// [`Limits`] are exceeded
+    pub fn blank(width: u32, height: u32, value: bool) -> Result<Bmp, BmpError> {
+        Bmp::blank_with_limits(width, height, value, &Limits::default())
+    }
+
+    /// Creates a Bmp like [`Bmp::blank`] enforcing the given `limits`
+    pub fn blank_with_limits(
+        width: u32,
+        height: u32,
+        value: bool,
+        limits: &Limits,
+    ) -> Result<Bmp, BmpError> {
+        limits.check(width, height)?;
+        let mut bmp = Bmp::zeroed(width, height);
+        if value {
+            bmp.fill_rect(0, 0, width, height, true);
+        }
+        Ok(bmp)
+    }
+
+    /// Creates a Bmp of `width` x `height` pixels where the pixel at `row` and `column` is the
+    /// value returned by `f(row, column)`, erroring if the default [`Limits`] are exceeded
+    pub fn from_fn<F: FnMut(u32, u32) -> bool>(
+        width: u32,
+        height: u32,
+        f: F,
+    ) -> Result<Bmp, BmpError> {
+        Bmp::from_fn_with_limits(width, height, f, &Limits::default())
+    }
+
+    /// Creates a Bmp like [`Bmp::from_fn`] enforcing the given `limits`
+    pub fn from_fn_with_limits<F: FnMut(u32, u32) -> bool>(
+        width: u32,
+        height: u32,
+        mut f: F,
+        limits: &Limits,
+    ) -> Result<Bmp, BmpError> {
+        limits.check(width, height)?;
+        let mut bmp = Bmp::zeroed(width, height);
+        for row in 0..height {
+            let words = bmp.row_mut(row as usize);
+            for column in 0..width {
+                if f(row, column) {
+                    bit::set_bit(words, column as usize, true);
+                }
+            }
         }
+        Ok(bmp)
+    }
+
+    /// Creates a Bmp with all the pixels white, sizes must be already checked
+    fn zeroed(width: u32, height: u32) -> Bmp {
+        let words = bit::words_for(width as usize) * height as usize;
+        Bmp {
+            width,
+            height,
+            data: vec![0u64; words],
+            meta: Metadata::default(),
+        }
+    }
+
+    /// Creates a Bmp with all the pixels white and the same metadata of `self`
+    fn zeroed_like(&self, width: u32, height: u32) -> Bmp {
+        Bmp {
+            meta: self.meta.clone(),
+            ..Bmp::zeroed(width, height)
+        }
+    }
+
+    /// return the number of words used by every row
+    fn stride(&self) -> usize {
+        bit::words_for(self.width as usize)
+    }
+
+    /// return the words containing the row `i`
+    fn row(&self, i: usize) -> &[u64] {
+        let stride = self.stride();
+        &self.data[i * stride..(i + 1) * stride]
+    }
+
+    /// return the mutable words containing the row `i`
+    fn row_mut(&mut self, i: usize) -> &mut [u64] {
+        let stride = self.stride();
+        &mut self.data[i * stride..(i + 1) * stride]
+    }
+
+    /// return the colour space information, present if read from a file with a BITMAPV4HEADER
+    /// or BITMAPV5HEADER
+    pub fn color_space(&self) -> Option<&ColorSpace> {
+        self.meta.color_space.as_ref()
+    }
+
+    /// return the palette of the file this Bmp has been read from
+    pub fn palette(&self) -> Option<Palette> {
+        self.meta.palette
+    }
+
+    /// set the palette written by [`Bmp::write`]
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.meta.palette = Some(palette);
+    }
+
+    /// return the resolution of the file this Bmp has been read from, if declared in the header
+    pub fn resolution(&self) -> Option<Resolution> {
+        self.meta.resolution
+    }
+
+    /// set the resolution written by [`Bmp::write`]
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.meta.resolution = Some(resolution);
     }
 
     /// return the Bmp height in pixel
-    pub fn height(&self) -> u16 {
-        self.rows.len() as u16
+    pub fn height(&self) -> u32 {
+        self.height
     }
 
     /// return the Bmp width in pixel
-    pub fn width(&self) -> u16 {
-        self.rows[0].len() as u16
+    pub fn width(&self) -> u32 {
+        self.width
     }
 
-    /// return the pixel situated at (i,j), where (0,0) is the upper-left corner
-    /// could panic if i > self.height() || j > self.width()
-    pub fn get(&self, i: u16, j: u16) -> bool {
-        self.rows[i as usize][j as usize]
+    /// return the pixel situated at `row` and `column`, where (0,0) is the upper-left corner
+    /// panics if row >= self.height() || column >= self.width()
+    pub fn get(&self, row: u32, column: u32) -> bool {
+        assert!(
+            row < self.height && column < self.width,
+            "pixel ({}, {}) out of bounds",
+            row,
+            column
+        );
+        bit::get_bit(self.row(row as usize), column as usize)
+    }
+
+    /// return an iterator over the rows, from the upper to the lower one, every row being an
+    /// iterator over its pixels from left to right, `true` being dark
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = bool> + '_> + '_ {
+        let width = self.width as usize;
+        self.data
+            .chunks(self.stride())
+            .map(move |row| (0..width).map(move |j| bit::get_bit(row, j)))
+    }
+
+    /// return an iterator over all the pixels as `(row, column, value)`, like the arguments of
+    /// [`Bmp::get`], from the upper-left corner row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32, bool)> + '_ {
+        self.rows().enumerate().flat_map(|(i, row)| {
+            row.enumerate()
+                .map(move |(j, value)| (i as u32, j as u32, value))
+        })
+    }
+
+    /// return an iterator over the coordinates `(row, column)` of the dark pixels, in the same
+    /// order of [`Bmp::pixels`], empty words of pixels are skipped entirely
+    pub fn ink_pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let stride = self.stride();
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, word)| **word != 0)
+            .flat_map(move |(index, word)| {
+                let row = (index / stride) as u32;
+                let start = (index % stride * bit::WORD_BITS) as u32;
+                let mut word = *word;
+                std::iter::from_fn(move || {
+                    if word == 0 {
+                        return None;
+                    }
+                    let offset = word.leading_zeros();
+                    word &= !(1 << (bit::WORD_BITS as u32 - 1 - offset));
+                    Some((row, start + offset))
+                })
+            })
+    }
+
+    /// return the pixel situated at `row` and `column` like [`Bmp::get`], or `None` if out of
+    /// bounds
+    pub fn try_get(&self, row: u32, column: u32) -> Option<bool> {
+        if row < self.height && column < self.width {
+            Some(bit::get_bit(self.row(row as usize), column as usize))
+        } else {
+            None
+        }
+    }
+
+    /// set the pixel situated at `row` and `column` to `value`, where (0,0) is the upper-left
+    /// corner
+    /// panics if row >= self.height() || column >= self.width()
+    pub fn set(&mut self, row: u32, column: u32, value: bool) {
+        assert!(
+            row < self.height && column < self.width,
+            "pixel ({}, {}) out of bounds",
+            row,
+            column
+        );
+        bit::set_bit(self.row_mut(row as usize), column as usize, value);
+    }
+
+    /// invert the pixel situated at `row` and `column`, where (0,0) is the upper-left corner
+    /// panics if row >= self.height() || column >= self.width()
+    pub fn toggle(&mut self, row: u32, column: u32) {
+        let value = self.get(row, column);
+        self.set(row, column, !value);
+    }
+
+    /// set all the pixels of the `width` x `height` rectangle with the upper-left corner at
+    /// `row` and `column` to `value`
+    /// panics if the rectangle exceeds the image
+    pub fn fill_rect(&mut self, row: u32, column: u32, width: u32, height: u32, value: bool) {
+        assert!(
+            row as u64 + height as u64 <= self.height as u64
+                && column as u64 + width as u64 <= self.width as u64,
+            "rectangle {}x{} at ({}, {}) out of bounds",
+            width,
+            height,
+            row,
+            column
+        );
+        for i in row as usize..row as usize + height as usize {
+            let words = self.row_mut(i);
+            if value {
+                bit::set_range(words, column as usize, width as usize);
+            } else {
+                bit::clear_range(words, column as usize, width as usize);
+            }
+        }
     }
 
     /// return a new Bmp where every pixel is multiplied by `mul`, erroring if mul is 0 or 1 or the
-    /// resulting image would be bigger than limits enforced by [crate::check_size]
+    /// resulting image would be bigger than the default [`Limits`]
     pub fn mul(&self, mul: u8) -> Result<Bmp, BmpError> {
+        self.mul_with_limits(mul, &Limits::default())
+    }
+
+    /// return a new Bmp like [`Bmp::mul`] enforcing the given `limits`
+    pub fn mul_with_limits(&self, mul: u8, limits: &Limits) -> Result<Bmp, BmpError> {
         if mul <= 1 {
-            return Err(BmpError::Generic);
+            return Err(BmpError::BadScaleFactor(mul));
         }
-        let mul = mul as u16;
-        let new_width = self.width().checked_mul(mul).ok_or(BmpError::Generic)?;
-        let new_height = self.height().checked_mul(mul).ok_or(BmpError::Generic)?;
-        check_size(new_width, new_height)?;
-        let mut rows = Vec::with_capacity(new_height as usize);
+        let mul = mul as u32;
+        let new_width = self.width().checked_mul(mul).ok_or(BmpError::Overflow)?;
+        let new_height = self.height().checked_mul(mul).ok_or(BmpError::Overflow)?;
+        limits.check(new_width, new_height)?;
+        let mut bmp = self.zeroed_like(new_width, new_height);
 
         let mul = mul as usize;
-        for i in 0..self.height() {
-            let mut row = Vec::with_capacity(new_width as usize);
-            for j in 0..self.width() {
-                row.extend(vec![self.get(i, j); mul]);
+        let stride = bmp.stride();
+        for i in 0..self.height as usize {
+            let first = i * mul;
+            let words = bmp.row_mut(first);
+            let row = self.row(i);
+            for j in 0..self.width as usize {
+                if bit::get_bit(row, j) {
+                    bit::set_range(words, j * mul, mul);
+                }
+            }
+            for k in 1..mul {
+                let start = first * stride;
+                bmp.data
+                    .copy_within(start..start + stride, (first + k) * stride);
             }
-            rows.extend(vec![row; mul]);
         }
 
-        Ok(Bmp { rows })
+        Ok(bmp)
     }
 
     /// return a new Bmp where every square is divided by `div`
     /// if all the square is not of the same color it errors
     pub fn div(&self, div: u8) -> Result<Bmp, BmpError> {
         if div <= 1 {
-            return Err(BmpError::Generic);
+            return Err(BmpError::BadScaleFactor(div));
         }
-        let div = div as u16;
+        let factor = div;
+        let div = div as u32;
         let new_height = self.height() / div;
         let new_width = self.width() / div;
         if new_height == 0 || new_width == 0 || self.height() % div != 0 || self.width() % div != 0
         {
-            return Err(BmpError::Generic);
+            return Err(BmpError::BadScaleFactor(factor));
         }
-        let mut new_rows = vec![];
+        let mut bmp = self.zeroed_like(new_width, new_height);
 
         let div = div as usize;
-        for rows in self.rows.chunks(div) {
-            let mut new_row = vec![];
-            for j in 0..div - 1 {
-                if rows[j] != rows[j + 1] {
-                    return Err(BmpError::Generic);
+        let stride = self.stride();
+        for (i, rows) in self.data.chunks(div * stride).enumerate() {
+            let first = &rows[..stride];
+            let rows_equal = rows.chunks(stride).all(|row| row == first);
+            let words = bmp.row_mut(i);
+            for j in 0..new_width as usize {
+                let start = j * div;
+                let value = bit::get_bit(first, start);
+                let block = start..start + div;
+                let uniform = block.clone().all(|k| bit::get_bit(first, k) == value)
+                    && (rows_equal
+                        || rows
+                            .chunks(stride)
+                            .all(|row| block.clone().all(|k| bit::get_bit(row, k) == value)));
+                if !uniform {
+                    return Err(BmpError::NonUniformBlock {
+                        row: (i * div) as u32,
+                        column: start as u32,
+                    });
                 }
-            }
-            for cols in rows[0].chunks(div) {
-                if cols.iter().all(|e| cols[0] == *e) {
-                    new_row.push(cols[0]);
-                } else {
-                    return Err(BmpError::Generic);
+                if value {
+                    bit::set_bit(words, j, true);
                 }
             }
-            new_rows.push(new_row);
         }
-        Ok(Bmp { rows: new_rows })
+        Ok(bmp)
     }
 
     fn div_with_greater_possible(&self, greater_start: u8) -> Bmp {
@@ -179,77 +1067,325 @@ impl Bmp {
         self.remove_white_border().div_with_greater_possible(12)
     }
 
-    /// return a new Bmp with `border_size` pixels around
+    /// return a new Bmp with `border_size` pixels around, erroring if the resulting image would
+    /// be bigger than the default [`Limits`]
     pub fn add_white_border(&self, border_size: u8) -> Result<Bmp, BmpError> {
-        let double_border = border_size as u16 * 2;
+        self.add_white_border_with_limits(border_size, &Limits::default())
+    }
+
+    /// return a new Bmp like [`Bmp::add_white_border`] enforcing the given `limits`
+    pub fn add_white_border_with_limits(
+        &self,
+        border_size: u8,
+        limits: &Limits,
+    ) -> Result<Bmp, BmpError> {
+        let double_border = border_size as u32 * 2;
         let width = self
             .width()
             .checked_add(double_border)
-            .ok_or(BmpError::Generic)?;
+            .ok_or(BmpError::Overflow)?;
         let height = self
             .height()
             .checked_add(double_border)
-            .ok_or(BmpError::Generic)?;
-        check_size(width, height)?;
-        let mut new_rows = Vec::with_capacity(height as usize);
+            .ok_or(BmpError::Overflow)?;
+        limits.check(width, height)?;
+        let mut bmp = self.zeroed_like(width, height);
         let border_size = border_size as usize;
-        new_rows.extend(vec![vec![false; width as usize]; border_size]);
-        for row in self.rows.iter() {
-            let mut new_row = Vec::with_capacity(width as usize);
-            new_row.extend(vec![false; border_size]);
-            new_row.extend(row);
-            new_row.extend(vec![false; border_size]);
-            new_rows.push(new_row);
+        for i in 0..self.height as usize {
+            let words = bmp.row_mut(i + border_size);
+            bit::copy_bits(self.row(i), 0, words, border_size, self.width as usize);
         }
-        new_rows.extend(vec![vec![false; width as usize]; border_size]);
 
-        Ok(Bmp { rows: new_rows })
+        Ok(bmp)
     }
 
     /// remove all the white border, if any
     pub fn remove_white_border(&self) -> Bmp {
-        let mut cur = self.clone();
-        loop {
-            match cur.remove_one_white_border() {
-                Ok(bmp) => cur = bmp,
-                Err(_) => return cur,
-            }
+        let border = self
+            .white_margin()
+            .min((self.width - 1) / 2)
+            .min((self.height - 1) / 2) as usize;
+        let width = self.width as usize - 2 * border;
+        let height = self.height as usize - 2 * border;
+        self.sub_image(border, border, width, height)
+    }
+
+    #[cfg(test)]
+    fn remove_one_white_border(&self) -> Option<Bmp> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        if width <= 2 || height <= 2 || self.white_margin() == 0 {
+            return None;
         }
+        Some(self.sub_image(1, 1, width - 2, height - 2))
     }
 
-    fn remove_one_white_border(&self) -> Result<Bmp, BmpError> {
-        if self.width() <= 2 || self.height() <= 2 {
-            return Err(BmpError::Generic);
+    /// return the minimum distance of the dark pixels from the image sides, [`u32::MAX`] if there
+    /// are no dark pixels
+    fn white_margin(&self) -> u32 {
+        match self.bounding_box() {
+            Some(rect) => rect
+                .row
+                .min(rect.column)
+                .min(self.height - rect.row - rect.height)
+                .min(self.width - rect.column - rect.width),
+            None => u32::MAX,
         }
-        let new_width = self.width() as usize - 2;
-        let new_height = self.height() as usize - 2;
-        let mut new_rows = vec![];
-        if self.rows[0].iter().all(|e| !*e)
-            && self.rows.last().unwrap().iter().all(|e| !*e)
-            && self.rows.iter().all(|r| !r[0])
-            && self.rows.iter().all(|r| !*r.last().unwrap())
-        {
-            for row in &self.rows[1..=new_height] {
-                new_rows.push(row[1..=new_width].to_vec())
+    }
+
+    /// return the smallest rectangle containing all the dark pixels, `None` if there are none
+    pub fn bounding_box(&self) -> Option<Rect> {
+        let mut rows = self
+            .data
+            .chunks(self.stride())
+            .enumerate()
+            .filter(|(_, row)| row.iter().any(|word| *word != 0));
+        let (top, first) = rows.next()?;
+        let mut bottom = top;
+        let mut left = usize::MAX;
+        let mut right = 0;
+        let mut extend = |row: &[u64]| {
+            let mut words = row.iter().enumerate().filter(|(_, word)| **word != 0);
+            if let Some((k, word)) = words.clone().next() {
+                left = left.min(k * bit::WORD_BITS + word.leading_zeros() as usize);
             }
-            Ok(Bmp { rows: new_rows })
-        } else {
-            Err(BmpError::Generic)
+            if let Some((k, word)) = words.next_back() {
+                right = right.max((k + 1) * bit::WORD_BITS - 1 - word.trailing_zeros() as usize);
+            }
+        };
+        extend(first);
+        for (i, row) in rows {
+            bottom = i;
+            extend(row);
         }
+        Some(Rect {
+            row: top as u32,
+            column: left as u32,
+            width: (right - left + 1) as u32,
+            height: (bottom - top + 1) as u32,
+        })
+    }
+
+    /// return a view of the `width` x `height` region with the upper-left corner at `row` and
+    /// `column`, erroring if the region is empty or exceeds the image
+    pub fn view(
+        &self,
+        row: u32,
+        column: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<BmpView<'_>, BmpError> {
+        let region = Rect {
+            row,
+            column,
+            width,
+            height,
+        };
+        if width == 0
+            || height == 0
+            || row as u64 + height as u64 > self.height as u64
+            || column as u64 + width as u64 > self.width as u64
+        {
+            return Err(BmpError::InvalidRegion(region));
+        }
+        Ok(BmpView { bmp: self, region })
+    }
+
+    /// return a new Bmp with the `width` x `height` region with the upper-left corner at `row`
+    /// and `column`, erroring if the region is empty or exceeds the image
+    pub fn crop(&self, row: u32, column: u32, width: u32, height: u32) -> Result<Bmp, BmpError> {
+        Ok(self.view(row, column, width, height)?.to_bmp())
+    }
+
+    /// return the `width` x `height` portion of the image starting at (`top`,`left`)
+    fn sub_image(&self, top: usize, left: usize, width: usize, height: usize) -> Bmp {
+        let mut bmp = self.zeroed_like(width as u32, height as u32);
+        for i in 0..height {
+            bit::copy_bits(self.row(top + i), left, bmp.row_mut(i), 0, width);
+        }
+        bmp
     }
 
     /// Return a struct implementing Display to visualize in terminal or in tests
-    pub fn display(&self) -> StringOutput {
+    pub fn display(&self) -> StringOutput<'_> {
         StringOutput(self)
     }
 
     /// Return inverted bitmap, black pixels become white and viceversa.
     pub fn inverse(&self) -> Bmp {
-        let mut new_vec = Vec::with_capacity(self.height() as usize);
-        for vec in self.rows.iter() {
-            new_vec.push(vec.iter().map(|e| !e).collect());
+        let mut bmp = self.clone();
+        let stride = self.stride();
+        let mask = bit::last_word_mask(self.width as usize);
+        for row in bmp.data.chunks_mut(stride) {
+            for word in row.iter_mut() {
+                *word = !*word;
+            }
+            row[stride - 1] &= mask;
+        }
+        bmp
+    }
+
+    /// return the transposed bitmap, where the pixel at (i,j) is moved to (j,i), the horizontal
+    /// and vertical resolutions are swapped
+    pub fn transpose(&self) -> Bmp {
+        let mut bmp = self.zeroed_like(self.height, self.width);
+        if let Some(resolution) = self.meta.resolution {
+            bmp.meta.resolution = Some(Resolution {
+                x: resolution.y,
+                y: resolution.x,
+            });
+        }
+        let height = self.height as usize;
+        let width = self.width as usize;
+        let stride = self.stride();
+        let mut block = [0u64; bit::WORD_BITS];
+        // every 64x64 block, read as 64 words of consecutive rows, is transposed and written
+        // as 64 words of consecutive rows of the result
+        for block_row in 0..bit::words_for(height) {
+            for block_column in 0..stride {
+                let top = block_row * bit::WORD_BITS;
+                for (k, word) in block.iter_mut().enumerate() {
+                    *word = if top + k < height {
+                        self.data[(top + k) * stride + block_column]
+                    } else {
+                        0
+                    };
+                }
+                bit::transpose(&mut block);
+                let left = block_column * bit::WORD_BITS;
+                for (k, word) in block.iter().enumerate().take(width.saturating_sub(left)) {
+                    bmp.row_mut(left + k)[block_row] = *word;
+                }
+            }
         }
-        Bmp::new(new_vec).unwrap()
+        bmp
+    }
+
+    /// return the bitmap mirrored left to right
+    pub fn flip_horizontal(&self) -> Bmp {
+        let mut bmp = self.zeroed_like(self.width, self.height);
+        let stride = self.stride();
+        let padding = stride * bit::WORD_BITS - self.width as usize;
+        let mut reversed = vec![0u64; stride];
+        for i in 0..self.height as usize {
+            for (word, original) in reversed.iter_mut().zip(self.row(i).iter().rev()) {
+                *word = original.reverse_bits();
+            }
+            for (k, word) in bmp.row_mut(i).iter_mut().enumerate() {
+                *word = bit::get_word(&reversed, padding + k * bit::WORD_BITS);
+            }
+        }
+        bmp
+    }
+
+    /// return the bitmap mirrored top to bottom
+    pub fn flip_vertical(&self) -> Bmp {
+        let mut bmp = self.zeroed_like(self.width, self.height);
+        let stride = self.stride();
+        for (row, original) in bmp
+            .data
+            .chunks_mut(stride)
+            .zip(self.data.chunks(stride).rev())
+        {
+            row.copy_from_slice(original);
+        }
+        bmp
+    }
+
+    /// return the bitmap rotated 90 degrees clockwise
+    pub fn rotate90(&self) -> Bmp {
+        self.transpose().flip_horizontal()
+    }
+
+    /// return the bitmap rotated 180 degrees
+    pub fn rotate180(&self) -> Bmp {
+        self.flip_horizontal().flip_vertical()
+    }
+
+    /// return the bitmap rotated 270 degrees clockwise, that is 90 degrees counterclockwise
+    pub fn rotate270(&self) -> Bmp {
+        self.transpose().flip_vertical()
+    }
+}
+
+impl<'a> BmpView<'a> {
+    /// return the region of the Bmp viewed
+    pub fn region(&self) -> Rect {
+        self.region
+    }
+
+    /// return the view height in pixel
+    pub fn height(&self) -> u32 {
+        self.region.height
+    }
+
+    /// return the view width in pixel
+    pub fn width(&self) -> u32 {
+        self.region.width
+    }
+
+    /// return the pixel situated at `row` and `column`, where (0,0) is the upper-left corner of
+    /// the view
+    /// panics if row >= self.height() || column >= self.width()
+    pub fn get(&self, row: u32, column: u32) -> bool {
+        assert!(
+            row < self.region.height && column < self.region.width,
+            "pixel ({}, {}) out of bounds",
+            row,
+            column
+        );
+        self.bmp
+            .get(self.region.row + row, self.region.column + column)
+    }
+
+    /// return the pixel situated at `row` and `column` like [`BmpView::get`], or `None` if out
+    /// of bounds
+    pub fn try_get(&self, row: u32, column: u32) -> Option<bool> {
+        if row < self.region.height && column < self.region.width {
+            Some(
+                self.bmp
+                    .get(self.region.row + row, self.region.column + column),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// return an iterator over the rows of the view, like [`Bmp::rows`]
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = bool> + 'a> + 'a {
+        let left = self.region.column as usize;
+        let right = left + self.region.width as usize;
+        self.bmp
+            .data
+            .chunks(self.bmp.stride())
+            .skip(self.region.row as usize)
+            .take(self.region.height as usize)
+            .map(move |row| (left..right).map(move |j| bit::get_bit(row, j)))
+    }
+
+    /// return an iterator over all the pixels of the view as `(row, column, value)`, like
+    /// [`Bmp::pixels`]
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32, bool)> + 'a {
+        self.rows().enumerate().flat_map(|(i, row)| {
+            row.enumerate()
+                .map(move |(j, value)| (i as u32, j as u32, value))
+        })
+    }
+
+    /// return a new Bmp with the pixels of the view and the metadata of the viewed Bmp
+    pub fn to_bmp(&self) -> Bmp {
+        let Rect {
+            row,
+            column,
+            width,
+            height,
+        } = self.region;
+        self.bmp.sub_image(
+            row as usize,
+            column as usize,
+            width as usize,
+            height as usize,
+        )
     }
 }
 
@@ -257,9 +1393,10 @@ impl Bmp {
 pub struct StringOutput<'a>(&'a Bmp);
 impl<'a> Display for StringOutput<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for row in self.0.rows.iter() {
-            for el in row.iter() {
-                if *el {
+        for i in 0..self.0.height as usize {
+            let row = self.0.row(i);
+            for j in 0..self.0.width as usize {
+                if bit::get_bit(row, j) {
                     write!(f, "#")?;
                 } else {
                     write!(f, ".")?;
@@ -272,15 +1409,20 @@ impl<'a> Display for StringOutput<'a> {
 }
 
 impl From<std::io::Error> for BmpError {
-    fn from(_: Error) -> Self {
-        BmpError::Generic
+    fn from(e: Error) -> Self {
+        BmpError::Io(e)
     }
 }
 
 impl BmpHeader {
     /// return bytes needed for `width` bits
     fn bytes_per_row(&self) -> u32 {
-        (self.width as u32 + 7) / 8
+        ((self.width as u64 + 7) / 8) as u32
+    }
+
+    /// return the index of the row stored at position `n` in the file, where 0 is the upper row
+    fn row_index(&self, n: usize) -> usize {
+        self.row_order.row_index(n, self.height)
     }
 
     /// return the padding
@@ -288,20 +1430,64 @@ impl BmpHeader {
         (4 - self.bytes_per_row() % 4) % 4
     }
 
+    /// return the size of the pixel data, padding included
+    fn data_size(&self) -> u64 {
+        (self.bytes_per_row() as u64 + self.padding() as u64) * self.height as u64
+    }
+
+    /// return the size of the whole file
+    fn total_size(&self) -> u64 {
+        HEADER_SIZE as u64 + self.data_size()
+    }
+
     /// return wether the bit 0 is to be considered black
     fn bg_is_zero(&self) -> bool {
         self.bg_is_zero
     }
+
+    /// return the palette with background and foreground colors in place of the palette indexes
+    fn palette(&self) -> Palette {
+        let (background, foreground) = if self.bg_is_zero { (1, 0) } else { (0, 1) };
+        Palette {
+            background: self.colors[background],
+            foreground: self.colors[foreground],
+        }
+    }
+
+    /// return the sizes declared in the header inconsistent with the pixel data, the image size
+    /// may be 0 for uncompressed bitmaps, while it's the only known size of compressed ones
+    fn size_warnings(&self) -> Vec<DecodeWarning> {
+        let mut warnings = vec![];
+        let data_size = if self.compression == Compression::Uncompressed {
+            // some writers over-report the image size, like the file size
+            if self.image_size != 0 && (self.image_size as u64) < self.data_size() {
+                warnings.push(DecodeWarning::InvalidImageSize(self.image_size));
+            }
+            self.data_size()
+        } else {
+            if self.image_size == 0 {
+                warnings.push(DecodeWarning::InvalidImageSize(self.image_size));
+            }
+            self.image_size as u64
+        };
+        // anything may follow the pixel data, such as the ICC profile of a BITMAPV5HEADER
+        if (self.file_size as u64) < self.pixel_offset as u64 + data_size {
+            warnings.push(DecodeWarning::InvalidFileSize(self.file_size));
+        }
+        warnings
+    }
 }
 
-/// arbitrary limit width * height < 1 million
-/// height and width must be > 0
-fn check_size(width: u16, height: u16) -> Result<u32, BmpError> {
-    let width_height = width as u32 * height as u32;
-    if width_height <= 1_000_000 && width > 0 && height > 0 {
-        Ok(width_height)
-    } else {
-        Err(BmpError::Size(width, height))
+impl From<DecodeWarning> for BmpError {
+    fn from(warning: DecodeWarning) -> Self {
+        match warning {
+            DecodeWarning::TruncatedData { row } => BmpError::TruncatedData {
+                row,
+                source: std::io::ErrorKind::UnexpectedEof.into(),
+            },
+            DecodeWarning::InvalidFileSize(size) => BmpError::InvalidFileSize(size),
+            DecodeWarning::InvalidImageSize(size) => BmpError::InvalidImageSize(size),
+        }
     }
 }
 
@@ -322,11 +1508,7 @@ mod test {
 
     #[test]
     fn test_padding() {
-        let mut header = BmpHeader {
-            height: 0,
-            width: 0,
-            bg_is_zero: false,
-        };
+        let mut header = BmpHeader::default();
         assert_eq!(header.padding(), 0);
 
         header.width = 1;
@@ -344,11 +1526,7 @@ mod test {
 
     #[test]
     fn test_bytes_per_row() {
-        let mut header = BmpHeader {
-            height: 0,
-            width: 0,
-            bg_is_zero: false,
-        };
+        let mut header = BmpHeader::default();
         assert_eq!(header.bytes_per_row(), 0);
 
         header.width = 1;
@@ -361,6 +1539,204 @@ mod test {
         assert_eq!(header.bytes_per_row(), 2);
     }
 
+    #[test]
+    fn test_resolution_dpi() {
+        assert_eq!(Resolution::from_dpi(300), Resolution { x: 11811, y: 11811 });
+        assert_eq!(Resolution::from_dpi(72).dpi(), (72, 72));
+        assert_eq!(Resolution::from_dpi(600).dpi(), (600, 600));
+        assert_eq!(Resolution::default().dpi(), (13, 13));
+    }
+
+    #[test]
+    fn test_pixel_editing() {
+        let mut bmp = Bmp::blank(70, 3, false).unwrap();
+        assert_eq!(Bmp::new(vec![vec![false; 70]; 3]).unwrap(), bmp);
+        assert_eq!(
+            Bmp::blank(70, 3, true).unwrap(),
+            Bmp::new(vec![vec![true; 70]; 3]).unwrap()
+        );
+        assert_eq!(Bmp::blank(70, 3, true).unwrap().inverse(), bmp);
+        assert!(Bmp::blank(0, 3, true).is_err());
+        assert!(Bmp::blank(1001, 1000, false).is_err());
+
+        bmp.set(1, 69, true);
+        assert!(bmp.get(1, 69));
+        assert_eq!(Some(true), bmp.try_get(1, 69));
+        assert_eq!(Some(false), bmp.try_get(1, 68));
+        assert_eq!(None, bmp.try_get(1, 70));
+        assert_eq!(None, bmp.try_get(3, 0));
+        bmp.toggle(1, 69);
+        bmp.toggle(2, 0);
+        assert!(!bmp.get(1, 69));
+        assert!(bmp.get(2, 0));
+
+        bmp.fill_rect(0, 60, 10, 2, true);
+        bmp.fill_rect(1, 62, 3, 1, false);
+        let expected = Bmp::from_fn(70, 3, |row, column| {
+            (row == 2 && column == 0)
+                || (column >= 60 && row < 2 && !(row == 1 && (62..65).contains(&column)))
+        })
+        .unwrap();
+        assert_eq!(expected, bmp);
+        bmp.fill_rect(0, 0, 70, 3, false);
+        bmp.fill_rect(3, 70, 0, 0, true);
+        assert_eq!(Bmp::blank(70, 3, false).unwrap(), bmp);
+
+        let stripes = Bmp::from_fn(3, 2, |row, column| row == 0 || column == 2).unwrap();
+        let expected = vec![vec![true, true, true], vec![false, false, true]];
+        assert_eq!(Bmp::new(expected).unwrap(), stripes);
+        assert!(Bmp::from_fn(3, 0, |_, _| true).is_err());
+
+        // A4 page at 600 DPI
+        let limits = Limits {
+            max_pixels: 35_000_000,
+            ..Default::default()
+        };
+        assert!(Bmp::blank(4961, 7016, false).is_err());
+        let page = Bmp::blank_with_limits(4961, 7016, true, &limits).unwrap();
+        assert!(page.get(7015, 4960));
+        let page = Bmp::from_fn_with_limits(4961, 7016, |row, _| row == 7015, &limits).unwrap();
+        assert!(page.get(7015, 0) && !page.get(0, 4960));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_out_of_bounds() {
+        Bmp::blank(2, 2, false).unwrap().set(0, 2, true);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_fill_rect_out_of_bounds() {
+        Bmp::blank(2, 2, false).unwrap().fill_rect(1, 1, 2, 1, true);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let bmp = random_bmp();
+        let rows: Vec<Vec<bool>> = bmp.rows().map(|row| row.collect()).collect();
+        assert_eq!(bmp.height() as usize, rows.len());
+        assert!(rows.iter().all(|row| row.len() == bmp.width() as usize));
+        assert_eq!(bmp, rows.iter().cloned().collect());
+
+        let mut count = 0;
+        for (i, j, value) in bmp.pixels() {
+            assert_eq!(bmp.get(i, j), value);
+            assert_eq!(rows[i as usize][j as usize], value);
+            count += 1;
+        }
+        assert_eq!(bmp.width() * bmp.height(), count);
+
+        let ink: Vec<(u32, u32)> = bmp
+            .pixels()
+            .filter(|(_, _, value)| *value)
+            .map(|(i, j, _)| (i, j))
+            .collect();
+        assert_eq!(ink, bmp.ink_pixels().collect::<Vec<_>>());
+
+        let bmp = Bmp::from_fn(130, 3, |row, column| {
+            column == 129 || (row == 1 && column == 64)
+        })
+        .unwrap();
+        let ink: Vec<_> = bmp.ink_pixels().collect();
+        assert_eq!(vec![(0, 129), (1, 64), (1, 129), (2, 129)], ink);
+        assert_eq!(0, Bmp::blank(100, 100, false).unwrap().ink_pixels().count());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_iter_invalid_rows() {
+        let _: Bmp = vec![vec![true], vec![true, false]].into_iter().collect();
+    }
+
+    #[test]
+    fn test_transforms() {
+        for bmp in &[
+            random_bmp(),
+            Bmp::from_fn(130, 70, |row, column| (row * column) % 7 == 1).unwrap(),
+        ] {
+            let (width, height) = (bmp.width(), bmp.height());
+            let transposed = bmp.transpose();
+            let flipped_h = bmp.flip_horizontal();
+            let flipped_v = bmp.flip_vertical();
+            let rotated90 = bmp.rotate90();
+            let rotated180 = bmp.rotate180();
+            let rotated270 = bmp.rotate270();
+            assert_eq!((height, width), (transposed.width(), transposed.height()));
+            assert_eq!((height, width), (rotated90.width(), rotated90.height()));
+            assert_eq!((height, width), (rotated270.width(), rotated270.height()));
+            for (i, j, value) in bmp.pixels() {
+                assert_eq!(value, transposed.get(j, i));
+                assert_eq!(value, flipped_h.get(i, width - 1 - j));
+                assert_eq!(value, flipped_v.get(height - 1 - i, j));
+                assert_eq!(value, rotated90.get(j, height - 1 - i));
+                assert_eq!(value, rotated180.get(height - 1 - i, width - 1 - j));
+                assert_eq!(value, rotated270.get(width - 1 - j, i));
+            }
+            // padding bits stay zero, compared by equality
+            assert_eq!(*bmp, transposed.transpose());
+            assert_eq!(*bmp, flipped_h.flip_horizontal());
+            assert_eq!(*bmp, rotated90.rotate270());
+            assert_eq!(*bmp, rotated90.rotate90().rotate180());
+            assert_eq!(bmp.inverse().rotate90(), rotated90.inverse());
+        }
+
+        let mut bmp = Bmp::new(vec![vec![true, false, false]]).unwrap();
+        bmp.set_resolution(Resolution { x: 100, y: 200 });
+        let rotated = bmp.rotate90();
+        let expected = Bmp::new(vec![vec![true], vec![false], vec![false]]).unwrap();
+        assert_eq!(expected, rotated);
+        assert_eq!(Some(Resolution { x: 200, y: 100 }), rotated.resolution());
+        assert_eq!(bmp.resolution(), bmp.rotate180().resolution());
+    }
+
+    #[test]
+    fn test_limits() {
+        let bmp = Bmp::new(vec![vec![true; 100]; 100]).unwrap();
+        assert!(matches!(bmp.mul(11), Err(BmpError::Size(1100, 1100))));
+        let limits = Limits {
+            max_pixels: 35_000_000,
+            ..Default::default()
+        };
+        assert_eq!(bmp.mul_with_limits(11, &limits).unwrap().width(), 1100);
+        let bmp = bmp.mul(8).unwrap();
+        assert!(bmp.add_white_border(200).is_err());
+        assert!(bmp.add_white_border_with_limits(200, &limits).is_ok());
+
+        let limits = Limits {
+            max_width: 50,
+            ..Default::default()
+        };
+        assert!(Bmp::new_with_limits(vec![vec![true; 100]; 10], &limits).is_err());
+        assert!(Bmp::new_with_limits(vec![vec![true; 10]; 100], &limits).is_ok());
+
+        // 2 words of 8 bytes for every row
+        let limits = Limits {
+            max_alloc: 16 * 10,
+            ..Default::default()
+        };
+        assert!(Limits::default().check(0, 10).is_err());
+        assert!(limits.check(128, 10).is_ok());
+        assert!(limits.check(129, 10).is_err());
+        assert!(limits.check(128, 11).is_err());
+        let limits = Limits {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: u64::MAX,
+            max_alloc: usize::MAX,
+        };
+        assert!(limits.check(u32::MAX, u32::MAX).is_err());
+        assert!(limits.check(i32::MAX as u32, 1).is_ok());
+        assert!(limits.check(1 << 31, 1).is_err());
+        assert!(limits.check(1, 1 << 31).is_err());
+        // the file size would exceed 4 GiB
+        assert!(limits.check(1 << 30, 40).is_err());
+        assert!(matches!(
+            Bmp::blank_with_limits(1 << 31, 1, false, &limits),
+            Err(BmpError::Size(2_147_483_648, 1))
+        ));
+    }
+
     #[test]
     fn test_mul() {
         let data = Bmp::new(vec![vec![false, true], vec![false, true]]).unwrap();
@@ -401,6 +1777,24 @@ mod test {
         assert_eq!(expected, data.div(2).unwrap());
     }
 
+    #[test]
+    fn test_div_errors() {
+        let data = Bmp::new(vec![
+            vec![false, false, true, true],
+            vec![false, false, true, true],
+            vec![false, false, true, true],
+            vec![false, false, true, false],
+        ])
+        .unwrap();
+        assert!(matches!(data.div(1), Err(BmpError::BadScaleFactor(1))));
+        assert!(matches!(data.div(3), Err(BmpError::BadScaleFactor(3))));
+        assert!(matches!(
+            data.div(2),
+            Err(BmpError::NonUniformBlock { row: 2, column: 2 })
+        ));
+        assert!(matches!(data.mul(0), Err(BmpError::BadScaleFactor(0))));
+    }
+
     #[test]
     fn test_mul_div() {
         let expected = random_bmp();
@@ -508,6 +1902,55 @@ mod test {
         assert_eq!(bmp1, bmp5.remove_white_border());
     }
 
+    #[test]
+    fn test_crop_view_bounding_box() {
+        let bmp = random_bmp();
+        let (width, height) = (bmp.width(), bmp.height());
+        let (row, column) = (height / 4, width / 3);
+        let (w, h) = (width - column, height - row);
+        let view = bmp.view(row, column, w, h).unwrap();
+        let cropped = bmp.crop(row, column, w, h).unwrap();
+        assert_eq!((w, h), (view.width(), view.height()));
+        assert_eq!(cropped, view.to_bmp());
+        assert_eq!(
+            cropped.pixels().collect::<Vec<_>>(),
+            view.pixels().collect::<Vec<_>>()
+        );
+        for (i, pixels) in view.rows().enumerate() {
+            for (j, value) in pixels.enumerate() {
+                assert_eq!(value, bmp.get(row + i as u32, column + j as u32));
+                assert_eq!(Some(value), view.try_get(i as u32, j as u32));
+            }
+        }
+        assert_eq!(None, view.try_get(h, 0));
+        assert_eq!(bmp, bmp.crop(0, 0, width, height).unwrap());
+        assert!(matches!(
+            bmp.crop(0, 1, width, 1),
+            Err(BmpError::InvalidRegion(Rect { column: 1, .. }))
+        ));
+        assert!(bmp.crop(0, 0, 0, 1).is_err());
+        assert!(bmp.crop(0, u32::MAX, 2, 1).is_err());
+
+        let mut bmp = Bmp::blank(200, 50, false).unwrap();
+        assert_eq!(None, bmp.bounding_box());
+        assert_eq!(bmp.crop(24, 24, 152, 2).unwrap(), bmp.remove_white_border());
+        bmp.set(10, 70, true);
+        bmp.set(30, 130, true);
+        bmp.set(40, 100, true);
+        let rect = Rect {
+            row: 10,
+            column: 70,
+            width: 61,
+            height: 31,
+        };
+        assert_eq!(Some(rect), bmp.bounding_box());
+        let trimmed = bmp
+            .crop(rect.row, rect.column, rect.width, rect.height)
+            .unwrap();
+        assert_eq!(Some(61), trimmed.bounding_box().map(|rect| rect.width));
+        assert_eq!(bmp.crop(9, 9, 182, 32).unwrap(), bmp.remove_white_border());
+    }
+
     #[test]
     fn test_div_with_greater_possible() {
         let bmp = Bmp::read(File::open("test_bmp/monochrome_image.bmp").unwrap()).unwrap();
@@ -531,10 +1974,34 @@ mod test {
         assert_eq!(bmp, inverted.inverse());
     }
 
+    #[test]
+    fn test_packed_rows() {
+        let rows: Vec<Vec<bool>> = (0..3)
+            .map(|i| (0..130).map(|j| (i + j) % 3 == 0).collect())
+            .collect();
+        let bmp = Bmp::new(rows.clone()).unwrap();
+        for (i, row) in rows.iter().enumerate() {
+            for (j, pixel) in row.iter().enumerate() {
+                assert_eq!(*pixel, bmp.get(i as u32, j as u32));
+            }
+        }
+        assert_eq!(bmp.data.len(), 3 * 3);
+
+        let white = Bmp::new(vec![vec![false; 130]; 3]).unwrap();
+        let black = Bmp::new(vec![vec![true; 130]; 3]).unwrap();
+        assert_eq!(white.inverse(), black);
+
+        let bordered = bmp.add_white_border(7).unwrap();
+        assert_eq!(bordered.width(), 144);
+        assert!(bordered.get(7, 7));
+        assert!(!bordered.get(7, 8));
+        assert_eq!(bordered.remove_white_border(), bmp);
+    }
+
     fn random_bmp() -> Bmp {
         let mut rng = rand::thread_rng();
-        let width: u16 = rng.gen_range(1, 20);
-        let height: u16 = rng.gen_range(1, 20);
+        let width: u32 = rng.gen_range(1, 150);
+        let height: u32 = rng.gen_range(1, 150);
         let mut data = vec![];
         for _ in 0..height {
             let row: Vec<bool> = (0..width).map(|_| rng.gen()).collect();
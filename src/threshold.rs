@@ -0,0 +1,98 @@
+use crate::{Rgb, Threshold};
+
+/// return the luminance of `color` from 0 (black) to 255 (white)
+pub fn luminance(color: Rgb) -> u8 {
+    (color.luminance() / 1000) as u8
+}
+
+/// return the bits of `pixel` selected by `mask`, scaled from 0 to 255
+pub fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let value = ((pixel & mask) >> shift) as u64;
+    (value * 255 / max) as u8
+}
+
+/// return the luminance under which the pixels are dark
+pub fn limit(threshold: Threshold, luminances: &[u8]) -> u16 {
+    match threshold {
+        Threshold::Fixed(value) => value as u16,
+        Threshold::Otsu => otsu(luminances) as u16 + 1,
+    }
+}
+
+/// return the greatest luminance of the dark pixels according to Otsu's method, 127 if all the
+/// pixels have the same luminance
+fn otsu(luminances: &[u8]) -> u8 {
+    let mut histogram = [0u64; 256];
+    for luminance in luminances {
+        histogram[*luminance as usize] += 1;
+    }
+    let total = luminances.len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, count)| i as f64 * *count as f64)
+        .sum();
+
+    let mut best = 127;
+    let mut best_variance = 0.0;
+    let mut weight_dark = 0.0;
+    let mut sum_dark = 0.0;
+    for (i, count) in histogram.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        weight_dark += *count as f64;
+        sum_dark += i as f64 * *count as f64;
+        let weight_light = total - weight_dark;
+        if weight_light <= 0.0 {
+            break;
+        }
+        let mean_dark = sum_dark / weight_dark;
+        let mean_light = (sum - sum_dark) / weight_light;
+        let variance = weight_dark * weight_light * (mean_dark - mean_light).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = i as u8;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use crate::threshold::{channel, limit, luminance, otsu};
+    use crate::{Rgb, Threshold};
+
+    #[test]
+    fn test_channel() {
+        assert_eq!(channel(0x7C00, 0x7C00), 255);
+        assert_eq!(channel(0x0400, 0x7C00), 8);
+        assert_eq!(channel(0x12_34_56, 0xFF_00), 0x34);
+        assert_eq!(channel(0xFFFF_FFFF, 0), 0);
+        assert_eq!(channel(0xFFFF_FFFF, 0xFFFF_FFFF), 255);
+        assert_eq!(luminance(Rgb::WHITE), 255);
+        assert_eq!(luminance(Rgb::BLACK), 0);
+    }
+
+    #[test]
+    fn test_otsu() {
+        let mut luminances = vec![60u8; 100];
+        luminances.extend(vec![200u8; 300]);
+        assert_eq!(otsu(&luminances), 60);
+        assert_eq!(limit(Threshold::Otsu, &luminances), 61);
+        assert_eq!(limit(Threshold::Fixed(50), &luminances), 50);
+
+        luminances.extend(vec![70u8; 20]);
+        luminances.extend(vec![190u8; 20]);
+        let value = otsu(&luminances);
+        assert!((70..190).contains(&value));
+
+        assert_eq!(otsu(&[255; 10]), 127);
+        assert_eq!(otsu(&[]), 127);
+    }
+}